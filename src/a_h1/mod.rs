@@ -1,20 +1,32 @@
-use crate::tcp::Stream;
-use async_std::io;
-pub use http_types::{
-    headers::{HeaderName, HeaderValue},
-    Body,
+use crate::{
+    pool::{ConnKey, Permit, Pool, PoolKey, Poolable},
+    request::Options,
+    tcp::{Forward, Stream},
+    timeout::{timeout, TimeoutKind},
 };
+use async_std::io::{self, BufRead, Read, Write};
+pub use http_types::headers::{HeaderName, HeaderValue};
 use http_types::{
-    headers::{HeaderValues, Iter as HttpHeaderIter},
-    Method, Request, Response, Url,
+    headers::{
+        HeaderValues, Iter as HttpHeaderIter, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH,
+        CONTENT_TYPE, HOST, TRANSFER_ENCODING,
+    },
+    Body as HttpBody, Method, Request, Response, Url,
 };
 use serde::Serialize;
 use std::convert::{TryFrom, TryInto};
+use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Req {
     req: Request,
+    pool: Option<Pool>,
+    /// the body can only be sent once
+    streamed: bool,
 }
 
 impl<M, U> TryFrom<(M, U)> for crate::Request
@@ -27,13 +39,274 @@ where
 
     fn try_from(value: (M, U)) -> Result<Self, Self::Error> {
         let req = Request::new(value.0.try_into()?, value.1);
-        Ok(crate::Request(Req { req }))
+        Ok(crate::Request(
+            Req {
+                req,
+                pool: None,
+                streamed: false,
+            },
+            Default::default(),
+        ))
     }
 }
 impl Req {
     fn init(method: Method, uri: &str) -> Req {
         let req = Request::new(method, uri);
-        Req { req }
+        Req {
+            req,
+            pool: None,
+            streamed: false,
+        }
+    }
+    /// what to connect to: is https, host, port
+    fn conn_key(&self) -> Result<ConnKey, Error> {
+        let tls = match self.req.url().scheme() {
+            "https" => true,
+            "http" => false,
+            _ => return Err(Error::Scheme),
+        };
+
+        let host = match self.req.host() {
+            None => return Err(Error::UndefinedHost),
+            Some(host) => host,
+        };
+        let port = match self.req.url().port() {
+            None => {
+                if tls {
+                    443
+                } else {
+                    80
+                }
+            }
+            Some(port) => port,
+        };
+        Ok((tls, host.to_string(), port))
+    }
+}
+
+/// `async_h1::connect`, but in absolute-form if `stream` goes to a forwarding HTTP proxy.
+///
+/// The encoder of async_h1 only writes the origin-form, so the request is written here
+async fn send<RW>(
+    mut stream: RW,
+    mut req: Request,
+    forward: Option<Forward>,
+) -> http_types::Result<Response>
+where
+    RW: Read + Write + Send + Sync + Unpin + 'static,
+{
+    use async_std::io::WriteExt;
+    let Some(forward) = forward else {
+        return async_h1::connect(stream, req).await;
+    };
+    if let Some(authorization) = forward.authorization {
+        req.insert_header("Proxy-Authorization", authorization);
+    }
+    stream.write_all(&forward_head(&mut req)?).await?;
+    let mut body = req.take_body();
+    if body.len().is_some() {
+        io::copy(&mut body, &mut stream).await?;
+    } else {
+        write_chunked(body, &mut stream).await?;
+    }
+    stream.flush().await?;
+    async_h1::client::decode(stream).await
+}
+/// request line in absolute-form and the headers, as `async_h1::client::Encoder` would add them
+fn forward_head(req: &mut Request) -> io::Result<Vec<u8>> {
+    use std::io::Write;
+    let mut target = req.url().clone();
+    target.set_fragment(None);
+    let _ = target.set_username("");
+    let _ = target.set_password(None);
+
+    if req.header(HOST).is_none() {
+        let host = target
+            .host_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing hostname"))?;
+        match target.port() {
+            Some(port) => req.insert_header(HOST, format!("{}:{}", host, port)),
+            None => req.insert_header(HOST, host),
+        };
+    }
+    match req.len() {
+        Some(len) => req.insert_header(CONTENT_LENGTH, len.to_string()),
+        None => req.insert_header(TRANSFER_ENCODING, "chunked"),
+    };
+
+    let mut head = Vec::with_capacity(128);
+    write!(head, "{} {} HTTP/1.1\r\n", req.method(), target)?;
+    let mut headers: Vec<_> = req.iter().collect();
+    headers.sort_unstable_by_key(|(h, _)| if **h == HOST { "0" } else { h.as_str() });
+    for (header, values) in headers {
+        for value in values.iter() {
+            write!(head, "{}: {}\r\n", header, value)?;
+        }
+    }
+    head.extend_from_slice(b"\r\n");
+    Ok(head)
+}
+/// copy `body` to `stream` in chunked transfer coding
+async fn write_chunked<R, W>(mut body: R, stream: &mut W) -> io::Result<()>
+where
+    R: Read + Unpin,
+    W: Write + Unpin,
+{
+    use async_std::io::{ReadExt, WriteExt};
+    let mut buf = vec![0; 8192];
+    loop {
+        let n = body.read(&mut buf).await?;
+        stream.write_all(format!("{:X}\r\n", n).as_bytes()).await?;
+        if n == 0 {
+            return stream.write_all(b"\r\n").await;
+        }
+        stream.write_all(&buf[..n]).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+}
+
+/// A HTTP/1.1 connection that can be used for another request,
+/// once the body of the last response was read
+#[derive(Clone)]
+pub(crate) struct Connection {
+    stream: Arc<Mutex<Stream>>,
+    /// given back to the pool, once all clones are gone
+    _permit: Arc<Permit>,
+}
+impl Connection {
+    fn new(stream: Stream, permit: Permit) -> Connection {
+        Connection {
+            stream: Arc::new(Mutex::new(stream)),
+            _permit: Arc::new(permit),
+        }
+    }
+    /// check that the server did not close the connection
+    fn is_usable(&self) -> bool {
+        //the server should not send anything. If it closed the connection, read returns 0
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut buf = [0u8];
+        match self.stream.lock() {
+            Ok(mut s) => Pin::new(&mut *s).poll_read(&mut cx, &mut buf).is_pending(),
+            Err(_) => false,
+        }
+    }
+    /// put the connection into `pool`, once the response body is read.
+    /// If the body is dropped before that, so is the connection
+    fn release_after_body(self, resp: &mut Response, pool: Pool, key: PoolKey) {
+        if resp.len() == Some(0) {
+            pool.put(key, self);
+            return;
+        }
+        let has_content_type = resp.header(CONTENT_TYPE).is_some();
+        let body = resp.take_body();
+        let len = body.len();
+        resp.set_body(HttpBody::from_reader(
+            ReleaseOnEof {
+                body,
+                left: len,
+                release: Some((pool, key, self)),
+            },
+            len,
+        ));
+        if !has_content_type {
+            //set_body added one
+            resp.remove_header(CONTENT_TYPE);
+        }
+    }
+    fn lock(&self) -> io::Result<std::sync::MutexGuard<'_, Stream>> {
+        self.stream
+            .lock()
+            .map_err(|_| io::Error::other("connection poisoned"))
+    }
+}
+impl Read for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.lock()?).poll_read(cx, buf)
+    }
+}
+impl Write for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.lock()?).poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.lock()?).poll_flush(cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.lock()?).poll_close(cx)
+    }
+}
+impl Poolable for Connection {
+    fn is_closed(&self) -> bool {
+        !self.is_usable()
+    }
+    fn is_ready(&self) -> bool {
+        self.is_usable()
+    }
+    fn share(&self) -> Option<Self> {
+        None
+    }
+}
+/// Response body that puts its connection into the pool, once it is read completely
+struct ReleaseOnEof {
+    body: HttpBody,
+    /// bytes left to read, if known
+    left: Option<usize>,
+    release: Option<(Pool, PoolKey, Connection)>,
+}
+impl ReleaseOnEof {
+    fn consumed(&mut self, amt: usize) {
+        if let Some(left) = self.left.as_mut() {
+            *left = left.saturating_sub(amt);
+        }
+        if amt == 0 || self.left == Some(0) {
+            release(&mut self.release);
+        }
+    }
+}
+fn release(release: &mut Option<(Pool, PoolKey, Connection)>) {
+    if let Some((pool, key, conn)) = release.take() {
+        pool.put(key, conn);
+    }
+}
+impl Read for ReleaseOnEof {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let r = Pin::new(&mut this.body).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = r {
+            if n != 0 || !buf.is_empty() {
+                this.consumed(n);
+            }
+        }
+        r
+    }
+}
+impl BufRead for ReleaseOnEof {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        let r = Pin::new(&mut this.body).poll_fill_buf(cx);
+        if matches!(r, Poll::Ready(Ok(b)) if b.is_empty()) {
+            release(&mut this.release);
+        }
+        r
+    }
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        Pin::new(&mut this.body).consume(amt);
+        if amt != 0 {
+            this.consumed(amt);
+        }
     }
 }
 impl crate::request::Requests for Req {
@@ -58,45 +331,41 @@ impl crate::request::Requests for Req {
     fn new(meth: &str, uri: &str) -> Result<Req, Error> {
         Ok(Self::init(Method::from_str(meth)?, uri))
     }
-    async fn send_request(self) -> Result<crate::Response, Error> {
-        let tls = match self.req.url().scheme() {
-            "https" => true,
-            "http" => false,
-            _ => return Err(Error::Scheme),
-        };
+    async fn send_request(self, options: &Options) -> Result<Resp, Error> {
+        let (tls, host, port) = self.conn_key()?;
+        let first_byte = options.timeouts.first_byte;
 
-        let host = match self.req.host() {
-            None => return Err(Error::UndefinedHost),
-            Some(host) => host,
-        };
-        let port = match self.req.url().port() {
+        let pool = self.pool.unwrap_or_else(crate::pool::pool);
+        let key = PoolKey::new((tls, host.clone(), port), options);
+        let conn = match pool.take(&key) {
+            Some(conn) => conn,
             None => {
-                if tls {
-                    443
-                } else {
-                    80
-                }
+                let permit = pool.permit().await;
+                Connection::new(Stream::connect(&host, port, tls, options).await?, permit)
             }
-            Some(port) => port,
         };
-        let transport = Stream::connect(host, port, tls).await?;
-
-        let resp = async_h1::connect(transport, self.req).await?;
-        //TODO implement clonable connection (RW) like FCGI
-        //check connection headers, connect method and upgrades
-        //free slot once body is consumed
+        let forward = conn.lock()?.forward().cloned();
+        let resp = send(conn.clone(), self.req, forward);
+        let mut resp = timeout(first_byte, TimeoutKind::FirstByte, resp).await??;
+        if resp
+            .header(CONNECTION)
+            .is_none_or(|c| !c.last().as_str().eq_ignore_ascii_case("close"))
+        {
+            conn.release_after_body(&mut resp, pool, key);
+        }
+        let idle = options.timeouts.body_idle;
 
-        #[cfg(not(all(feature = "mock_tests", test)))]
-        return Ok(crate::Response(Resp { resp }));
-        #[cfg(all(feature = "mock_tests", test))]
-        return Ok(crate::Response(Resp::Real(not_mocked::Resp { resp })));
+        #[cfg(not(feature = "mock"))]
+        return Ok(Resp { resp, idle });
+        #[cfg(feature = "mock")]
+        return Ok(Resp::Real(not_mocked::Resp { resp, idle }));
     }
     fn json<T: Serialize + ?Sized>(&mut self, json: &T) -> Result<(), Error> {
-        self.req.set_body(Body::from_json(&json)?);
+        self.req.set_body(HttpBody::from_json(&json)?);
         Ok(())
     }
     fn form<T: Serialize + ?Sized>(&mut self, data: &T) -> Result<(), Error> {
-        self.req.set_body(Body::from_form(&data)?);
+        self.req.set_body(HttpBody::from_form(&data)?);
         Ok(())
     }
     fn query<T: Serialize + ?Sized>(&mut self, query: &T) -> Result<(), Error> {
@@ -104,7 +373,9 @@ impl crate::request::Requests for Req {
         Ok(())
     }
     fn body<B: Into<Body>>(&mut self, body: B) -> Result<(), Error> {
-        self.req.set_body(body);
+        let body = body.into();
+        self.streamed = body.streamed;
+        self.req.set_body(body.body);
         Ok(())
     }
     fn set_header(&mut self, name: HeaderName, values: HeaderValue) -> Result<(), Error> {
@@ -115,12 +386,76 @@ impl crate::request::Requests for Req {
         self.req.append_header(name, values);
         Ok(())
     }
+    fn set_pool(&mut self, pool: Pool) {
+        self.pool = Some(pool);
+    }
+    fn method(&self) -> String {
+        self.req.method().to_string()
+    }
+    fn url(&self) -> String {
+        self.req.url().to_string()
+    }
+    async fn try_clone(&mut self) -> Option<Self> {
+        if self.streamed {
+            return None;
+        }
+        let mut req = self.req.clone();
+        if self.req.len() != Some(0) {
+            let body = self.req.take_body().into_bytes().await.ok()?;
+            self.req.set_body(body.clone());
+            req.set_body(body);
+        }
+        Some(Req {
+            req,
+            pool: self.pool.clone(),
+            streamed: false,
+        })
+    }
+    fn set_url(&mut self, url: &str) -> Result<(), Error> {
+        *self.req.url_mut() = Url::parse(url).map_err(|e| Error::Http(e.into()))?;
+        Ok(())
+    }
+    fn change_to_get(&mut self) {
+        if self.req.method() != Method::Head {
+            self.req.set_method(Method::Get);
+        }
+        self.req.set_body(HttpBody::empty());
+        self.streamed = false;
+        self.req.remove_header(CONTENT_TYPE);
+        self.req.remove_header(CONTENT_LENGTH);
+        self.req.remove_header(CONTENT_ENCODING);
+        self.req.remove_header(TRANSFER_ENCODING);
+    }
+    fn remove_header(&mut self, name: &str) {
+        self.req.remove_header(name);
+    }
 }
 mod not_mocked {
     use super::*;
     use serde::de::DeserializeOwned;
     pub struct Resp {
         pub(super) resp: Response,
+        /// body_idle timeout
+        pub(super) idle: Option<Duration>,
+    }
+    impl Resp {
+        /// Read the body, but fail if no data arrives within `idle`
+        async fn body(&mut self) -> Result<HttpBody, Error> {
+            let mut body = self.resp.take_body();
+            if self.idle.is_none() {
+                return Ok(body);
+            }
+            let mut v = Vec::new();
+            let mut buf = [0u8; 8192];
+            loop {
+                let read = io::ReadExt::read(&mut body, &mut buf);
+                match timeout(self.idle, TimeoutKind::BodyIdle, read).await?? {
+                    0 => break,
+                    n => v.extend_from_slice(&buf[..n]),
+                }
+            }
+            Ok(v.into())
+        }
     }
     impl crate::response::Responses for Resp {
         fn status(&self) -> u16 {
@@ -130,13 +465,29 @@ mod not_mocked {
             self.resp.status().canonical_reason()
         }
         async fn json<D: DeserializeOwned>(&mut self) -> Result<D, Error> {
-            Ok(self.resp.body_json().await?)
+            Ok(self.body().await?.into_json().await?)
         }
         async fn bytes(&mut self) -> Result<Vec<u8>, Error> {
-            Ok(self.resp.body_bytes().await?)
+            Ok(self.body().await?.into_bytes().await?)
         }
         async fn string(&mut self) -> Result<String, Error> {
-            Ok(self.resp.body_string().await?)
+            Ok(self.body().await?.into_string().await?)
+        }
+        fn into_stream(mut self) -> futures::stream::BoxStream<'static, io::Result<bytes::Bytes>> {
+            let idle = self.idle;
+            let body = self.resp.take_body();
+            let stream = futures::stream::try_unfold(body, move |mut body| async move {
+                let mut buf = vec![0u8; 8192];
+                let read = io::ReadExt::read(&mut body, &mut buf);
+                match timeout(idle, TimeoutKind::BodyIdle, read).await?? {
+                    0 => Ok(None),
+                    n => {
+                        buf.truncate(n);
+                        Ok(Some((buf.into(), body)))
+                    }
+                }
+            });
+            Box::pin(stream)
         }
         fn get_header(&self, name: HeaderName) -> Option<&HeaderValue> {
             self.resp.header(name).and_then(|v| v.iter().next())
@@ -153,28 +504,22 @@ mod not_mocked {
         }
     }
 }
-#[cfg(not(all(feature = "mock_tests", test)))]
+#[cfg(not(feature = "mock"))]
 pub use not_mocked::Resp;
-#[cfg(all(feature = "mock_tests", test))]
+#[cfg(feature = "mock")]
 pub type Resp = crate::mock::Resp<not_mocked::Resp>;
-#[cfg(all(feature = "mock_tests", test))]
+#[cfg(feature = "mock")]
 mod mocked {
     use super::*;
-    use async_std::task::block_on;
+    //Resp::json of a mocked response
     impl From<serde_json::Error> for Error {
         fn from(e: serde_json::Error) -> Self {
-            panic!("test with invalid json {}", e);
+            Self::Io(e.into())
         }
     }
     impl crate::mock::MockedRequest for Req {
-        /// on error, return full body
-        fn assert_body_bytes(&mut self, should_be: &[u8]) -> Result<(), Vec<u8>> {
-            let is = block_on(self.req.body_bytes()).unwrap_or_default();
-            if is != should_be {
-                Err(is.clone())
-            } else {
-                Ok(())
-            }
+        async fn body_bytes(&mut self) -> io::Result<Vec<u8>> {
+            self.req.body_bytes().await.map_err(io::Error::other)
         }
         fn get_headers(&self, name: &str) -> Option<Vec<crate::mock::MockHeaderValue>> {
             let name = HeaderName::from_string(name.to_string()).unwrap();
@@ -222,6 +567,66 @@ impl<'a> Iterator for HeaderIter<'a> {
     }
 }
 
+/// Body of a request and if it can be sent more than once
+#[derive(Debug)]
+pub struct Body {
+    body: HttpBody,
+    streamed: bool,
+}
+impl Body {
+    pub(crate) fn from_reader<R>(reader: R, len: Option<u64>) -> Self
+    where
+        R: Read + Send + Unpin + 'static,
+    {
+        let len = len.map(|l| l as usize);
+        Body {
+            body: HttpBody::from_reader(io::BufReader::new(Unshared(reader)), len),
+            streamed: true,
+        }
+    }
+    pub(crate) fn from_stream<S>(stream: S, len: Option<u64>) -> Self
+    where
+        S: futures::Stream<Item = io::Result<bytes::Bytes>> + Send + 'static,
+    {
+        use futures::TryStreamExt;
+        let reader = Unshared(Box::pin(stream)).into_async_read();
+        let len = len.map(|l| l as usize);
+        Body {
+            body: HttpBody::from_reader(reader, len),
+            streamed: true,
+        }
+    }
+}
+/// http-types wants bodies to be `Sync`.
+/// This only hands out its content mutably, so it does not need to be
+struct Unshared<T>(T);
+// SAFETY: the content is never accessed via `&Unshared`
+unsafe impl<T: Send> Sync for Unshared<T> {}
+impl<R: Read + Unpin> Read for Unshared<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+impl<S: futures::Stream + Unpin> futures::Stream for Unshared<S> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        Pin::new(&mut self.get_mut().0).poll_next(cx)
+    }
+}
+impl<T: Into<HttpBody>> From<T> for Body {
+    #[inline]
+    fn from(t: T) -> Self {
+        Body {
+            body: t.into(),
+            streamed: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
@@ -239,7 +644,12 @@ impl fmt::Display for Error {
 impl From<Error> for crate::Error {
     fn from(e: Error) -> Self {
         match e {
-            Error::Io(error) => Self::Io(error),
+            Error::Io(error) => error.into(),
+            //It might be an IO error. If so, return it as such
+            Error::Http(e) => match e.downcast::<io::Error>() {
+                Ok(io) => io.into(),
+                Err(e) => Self::Other(Error::Http(e)),
+            },
             e => Self::Other(e),
         }
     }
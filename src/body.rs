@@ -1,16 +1,63 @@
 use crate::imp;
 
-/*use std::task::{Poll, Context};
+use bytes::Bytes;
+use futures::{AsyncRead, Stream};
+use std::io;
 use std::pin::Pin;
-
-use futures_core::Stream;*/
-/*
-#[cfg(feature = "use_async_h1")]
-use async_std::io::prelude::{AsyncRead, AsyncBufRead};
-*/
+use std::task::{Context, Poll};
 
 /// A Body for the Request. You will most likely use [`Request::body`](./struct.Request.html#method.body) directly.
 pub struct Body(imp::Body);
+impl Body {
+    /// Stream the body from `reader`.
+    ///
+    /// If `len` is known, it is sent as `Content-Length`.
+    /// Otherwise the body is sent chunked (HTTP/1.1) or as it comes (HTTP/2).
+    ///
+    /// As the body can only be read once, redirects that need to resend it are not followed.
+    ///
+    /// `reader` implements the `AsyncRead` of `futures`. For the one of `tokio`, see [`Body::from_tokio_reader`].
+    /// ```
+    /// # use generic_async_http_client::{Request, Body, Error};
+    /// # async fn upload(file: async_std::fs::File) -> Result<(), Error> {
+    ///     let len = file.metadata().await?.len();
+    ///     let req = Request::put("http://example.com/").body(Body::from_reader(file, Some(len)))?;
+    ///     req.exec().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_reader<R>(reader: R, len: Option<u64>) -> Body
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        Body(imp::Body::from_reader(reader, len))
+    }
+    /// Like [`Body::from_reader`], but for a `tokio` reader like `tokio::fs::File`
+    #[cfg(feature = "use_hyper")]
+    pub fn from_tokio_reader<R>(reader: R, len: Option<u64>) -> Body
+    where
+        R: tokio::io::AsyncRead + Send + Unpin + 'static,
+    {
+        Body::from_reader(TokioReader(reader), len)
+    }
+    /// Stream the body from chunks of `stream`. The length is unknown,
+    /// so the body is sent chunked (HTTP/1.1) or as it comes (HTTP/2).
+    ///
+    /// As the body can only be read once, redirects that need to resend it are not followed.
+    pub fn from_stream<S>(stream: S) -> Body
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        Body(imp::Body::from_stream(stream, None))
+    }
+    /// like `from_stream`, but the length might be known
+    pub(crate) fn from_sized_stream<S>(stream: S, len: Option<u64>) -> Body
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        Body(imp::Body::from_stream(stream, len))
+    }
+}
 impl From<String> for Body {
     #[inline]
     fn from(t: String) -> Self {
@@ -49,50 +96,152 @@ impl From<imp::Body> for Body {
     }
 }
 
-/*/TODO Stream to server -> AsyncWrite for Body
-impl Stream for Body {
-    type Item = Result<&'a [u8], imp::Error>;
-
-    #[cfg(feature = "use_hyper")]
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Ok(self.0.poll_next(cx).map(|b|&b)?)
+/// Chunks of an `AsyncRead`
+pub(crate) struct ReaderStream<R> {
+    reader: R,
+    buf: Box<[u8]>,
+}
+impl<R> ReaderStream<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        ReaderStream {
+            reader,
+            buf: vec![0; 8192].into_boxed_slice(),
+        }
     }
-    #[cfg(feature = "use_async_h1")]
+}
+impl<R: AsyncRead + Unpin> Stream for ReaderStream<R> {
+    type Item = io::Result<Bytes>;
+
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Ok(self.0.poll_fill_buf(cx)?)
+        let this = self.get_mut();
+        match Pin::new(&mut this.reader).poll_read(cx, &mut this.buf) {
+            Poll::Ready(Ok(0)) => Poll::Ready(None),
+            Poll::Ready(Ok(n)) => Poll::Ready(Some(Ok(Bytes::copy_from_slice(&this.buf[..n])))),
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Pending => Poll::Pending,
+        }
     }
-}*/
-/*
-#[cfg(feature = "use_async_h1")]
-impl AsyncRead for Body {
-    #[allow(missing_doc_code_examples)]
+}
+
+/// The `futures::AsyncRead` of a `tokio::io::AsyncRead`
+#[cfg(feature = "use_hyper")]
+pub(crate) struct TokioReader<R>(pub(crate) R);
+#[cfg(feature = "use_hyper")]
+impl<R: tokio::io::AsyncRead + Unpin> AsyncRead for TokioReader<R> {
     fn poll_read(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
-        let mut buf = match self.length {
-            None => buf,
-            Some(length) if length == self.bytes_read => return Poll::Ready(Ok(0)),
-            Some(length) => {
-                let max_len = (length - self.bytes_read).min(buf.len());
-                &mut buf[0..max_len]
-            }
-        };
-
-        let bytes = ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf))?;
-        self.bytes_read += bytes;
-        Poll::Ready(Ok(bytes))
+        let mut buf = tokio::io::ReadBuf::new(buf);
+        match Pin::new(&mut self.get_mut().0).poll_read(cx, &mut buf) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
     }
 }
-#[cfg(feature = "use_async_h1")]
-impl AsyncBufRead for Body {
-    #[allow(missing_doc_code_examples)]
-    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&'_ [u8]>> {
-        self.project().reader.poll_fill_buf(cx)
+
+#[cfg(all(test, any(feature = "use_hyper", feature = "use_async_h1")))]
+mod tests {
+    use super::*;
+    use crate::tests::{
+        block_on, listen_somewhere, read_head, spawn, ReadExt, TcpListener, WriteExt,
+    };
+    use crate::Request;
+
+    async fn server(
+        listener: TcpListener,
+        head: &'static str,
+        body: &'static [u8],
+    ) -> io::Result<bool> {
+        let (mut stream, _) = listener.accept().await?;
+        let h = read_head(&mut stream).await?;
+        assert!(h.starts_with("PUT / HTTP/1.1\r\n"), "{}", h);
+        assert!(h.to_ascii_lowercase().contains(head), "{}", h);
+        let mut b = vec![0; body.len()];
+        stream.read_exact(&mut b).await?;
+        assert_eq!(b, body);
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await?;
+        Ok(true)
+    }
+    #[test]
+    fn from_reader() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener, "\r\ncontent-length: 5\r\n", b"abcde"));
+
+            let body = Body::from_reader(futures::io::Cursor::new(b"abcde"), Some(5));
+            let r = Request::put(&format!("http://{}:{}/", host, port)).body(body)?;
+            assert_eq!(r.exec().await?.status_code(), 200);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
     }
+    #[test]
+    fn from_stream() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(
+                listener,
+                "\r\ntransfer-encoding: chunked\r\n",
+                b"3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n",
+            ));
 
-    fn consume(mut self: Pin<&mut Self>, amt: usize) {
-        Pin::new(&mut self.reader).consume(amt)
+            let chunks = vec![
+                Ok(Bytes::from_static(b"abc")),
+                Ok(Bytes::from_static(b"de")),
+            ];
+            let body = Body::from_stream(futures::stream::iter(chunks));
+            let r = Request::put(&format!("http://{}:{}/", host, port)).body(body)?;
+            assert_eq!(r.exec().await?.status_code(), 200);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
     }
-}*/
+    /// the stream only has to be `Send`
+    #[test]
+    fn not_sync() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(
+                listener,
+                "\r\ntransfer-encoding: chunked\r\n",
+                b"3\r\nabc\r\n0\r\n\r\n",
+            ));
+
+            use futures::StreamExt;
+            let polled = std::cell::Cell::new(0);
+            let chunks = futures::stream::iter([&b"abc"[..]]).map(move |c| {
+                polled.set(polled.get() + 1);
+                Ok(Bytes::from_static(c))
+            });
+            let r = Request::put(&format!("http://{}:{}/", host, port))
+                .body(Body::from_stream(chunks))?;
+            let status = spawn(async move { r.exec().await.map(|r| r.status_code()) });
+            assert_eq!(status.await?, 200);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "use_hyper")]
+    #[test]
+    fn from_tokio_reader() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener, "\r\ncontent-length: 5\r\n", b"abcde"));
+
+            let body = Body::from_tokio_reader(std::io::Cursor::new(b"abcde"), Some(5));
+            let r = Request::put(&format!("http://{}:{}/", host, port)).body(body)?;
+            assert_eq!(r.exec().await?.status_code(), 200);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+use crate::HeaderValue;
+use cookie_store::CookieStore;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Stores cookies from `Set-Cookie` headers and adds them to later requests.
+///
+/// Domain, path, secure and expiry attributes are honored.
+/// `SameSite` cookies are not sent, when a redirect leads to a different site.
+///
+/// Clones of a `CookieJar` share the same cookies.
+/// ```
+/// # use generic_async_http_client::{Request, CookieJar, Error};
+/// # async fn login() -> Result<(), Error> {
+///     let jar = CookieJar::new();
+///     Request::post("http://example.com/login").cookie_jar(&jar).exec().await?;
+///     //sends the session cookie
+///     Request::get("http://example.com/").cookie_jar(&jar).exec().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct CookieJar(Arc<Mutex<CookieStore>>);
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar::default()
+    }
+    /// The name and value of all cookies that would be sent to `url`
+    pub fn cookies(&self, url: &str) -> Vec<(String, String)> {
+        let Ok(url) = Url::parse(url) else {
+            return Vec::new();
+        };
+        let store = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        store
+            .get_request_values(&url)
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+    /// Remove all cookies
+    pub fn clear(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+    /// Store the `Set-Cookie` headers of a response from `url`
+    pub(crate) fn store<'a>(&self, url: &str, set_cookie: impl Iterator<Item = &'a HeaderValue>) {
+        let Ok(url) = Url::parse(url) else {
+            return;
+        };
+        let mut store = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for c in set_cookie {
+            match c.as_str() {
+                Ok(c) => {
+                    if let Err(e) = store.parse(c, &url) {
+                        log::debug!("ignoring cookie: {}", e);
+                    }
+                }
+                Err(_) => log::debug!("ignoring non UTF-8 cookie"),
+            }
+        }
+    }
+    /// Value of the `Cookie` header for a request to `url`.
+    ///
+    /// `initiator` is the URL of the first request, if `url` is the target of a redirect
+    pub(crate) fn header(
+        &self,
+        url: &str,
+        initiator: Option<&str>,
+        method: &str,
+    ) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        let cross_site = initiator.is_some_and(|i| !same_site(i, &url));
+        let safe_method = method == "GET" || method == "HEAD";
+        let store = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let mut cookies = store.matches(&url);
+        //longer paths first (RFC 6265 5.4)
+        cookies.sort_by_key(|c| std::cmp::Reverse(c.path.as_ref().len()));
+        let mut header = String::new();
+        for c in cookies {
+            match c.same_site() {
+                Some(s) if cross_site && s.is_strict() => continue,
+                Some(s) if cross_site && s.is_lax() && !safe_method => continue,
+                _ => {}
+            }
+            if !header.is_empty() {
+                header.push_str("; ");
+            }
+            header.push_str(c.name());
+            header.push('=');
+            header.push_str(c.value());
+        }
+        if header.is_empty() {
+            None
+        } else {
+            Some(header)
+        }
+    }
+}
+impl std::fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let count = self
+            .0
+            .lock()
+            .map(|s| s.iter_unexpired().count())
+            .unwrap_or_default();
+        f.debug_struct("CookieJar")
+            .field("cookies", &count)
+            .finish()
+    }
+}
+/// scheme and host are the same
+fn same_site(a: &str, b: &Url) -> bool {
+    match Url::parse(a) {
+        Ok(a) => a.scheme() == b.scheme() && a.host_str() == b.host_str(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    /// cookies with the same path have no defined order
+    fn header(jar: &CookieJar, url: &str, initiator: Option<&str>, method: &str) -> Vec<String> {
+        let mut v: Vec<String> = jar
+            .header(url, initiator, method)
+            .map(|h| h.split("; ").map(String::from).collect())
+            .unwrap_or_default();
+        v.sort();
+        v
+    }
+    fn store(jar: &CookieJar, url: &str, cookies: &[&'static str]) {
+        let cookies: Vec<HeaderValue> = cookies.iter().map(|c| (*c).try_into().unwrap()).collect();
+        jar.store(url, cookies.iter());
+    }
+    #[test]
+    fn matching() {
+        let jar = CookieJar::new();
+        store(
+            &jar,
+            "https://sub.example.com/a/b",
+            &[
+                "host=1",
+                "domain=2; Domain=example.com; Path=/",
+                "path=3; Path=/c",
+                "secure=4; Secure",
+                "expired=5; Max-Age=0",
+                "other=6; Domain=other.com",
+            ],
+        );
+        assert_eq!(
+            header(&jar, "https://sub.example.com/a/x", None, "GET"),
+            vec!["domain=2", "host=1", "secure=4"]
+        );
+        assert_eq!(
+            header(&jar, "http://sub.example.com/c/x", None, "GET"),
+            vec!["domain=2", "path=3"]
+        );
+        assert_eq!(
+            header(&jar, "http://example.com/", None, "GET"),
+            vec!["domain=2"]
+        );
+        assert_eq!(
+            header(&jar, "http://other.com/", None, "GET"),
+            Vec::<String>::new()
+        );
+    }
+    #[test]
+    fn same_site() {
+        let jar = CookieJar::new();
+        store(
+            &jar,
+            "http://example.com/",
+            &["strict=1; SameSite=Strict", "lax=2; SameSite=Lax", "none=3"],
+        );
+        assert_eq!(
+            header(
+                &jar,
+                "http://example.com/",
+                Some("http://example.com/a"),
+                "POST"
+            ),
+            vec!["lax=2", "none=3", "strict=1"]
+        );
+        assert_eq!(
+            header(
+                &jar,
+                "http://example.com/",
+                Some("http://other.com/"),
+                "GET"
+            ),
+            vec!["lax=2", "none=3"]
+        );
+        assert_eq!(
+            header(
+                &jar,
+                "http://example.com/",
+                Some("http://other.com/"),
+                "POST"
+            ),
+            vec!["none=3"]
+        );
+    }
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    #[test]
+    fn session() {
+        use crate::tests::{block_on, listen_somewhere, read_head, spawn, TcpListener, WriteExt};
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            let head = read_head(&mut stream).await?;
+            assert!(
+                !head.to_ascii_lowercase().contains("\r\ncookie:"),
+                "{}",
+                head
+            );
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nset-cookie: a=1; Path=/\r\nset-cookie: b=2; Path=/b\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.contains("\r\ncookie: b=2; a=1\r\n"), "{}", head);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let jar = CookieJar::new();
+            let session = crate::Session::new().cookie_jar(&jar);
+            session
+                .get(&format!("http://{}:{}/", host, port))
+                .exec()
+                .await?;
+            session
+                .get(&format!("http://{}:{}/b/c", host, port))
+                .exec()
+                .await?;
+            assert!(t.await?, "not cool");
+            assert_eq!(
+                jar.cookies(&format!("http://{}:{}/", host, port)),
+                vec![("a".to_string(), "1".to_string())]
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    #[test]
+    fn redirect() {
+        use crate::tests::{block_on, listen_somewhere, read_head, spawn, TcpListener, WriteExt};
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("GET /a HTTP/1.1\r\n"), "{}", head);
+            assert!(head.contains("\r\ncookie: a=1\r\n"), "{}", head);
+            stream
+                .write_all(b"HTTP/1.1 302 Found\r\nlocation: /b\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("GET /b HTTP/1.1\r\n"), "{}", head);
+            assert!(
+                !head.to_ascii_lowercase().contains("\r\ncookie:"),
+                "{}",
+                head
+            );
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let jar = CookieJar::new();
+            let url = format!("http://{}:{}/a", host, port);
+            store(&jar, &url, &["a=1; Path=/a"]);
+            let resp = crate::Request::get(&url).cookie_jar(&jar).exec().await?;
+            assert_eq!(resp.status_code(), 200);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
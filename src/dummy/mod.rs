@@ -1,16 +1,65 @@
+use crate::request::Options;
+use bytes::Bytes;
+use futures::Stream;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::convert::TryFrom;
+use std::io;
+use std::pin::Pin;
 
 static ERR_MSG: &str = "No HTTP backend was selected";
 
-pub struct Body(Vec<u8>);
+/// Body of a request. There is nothing to send it to, it is only read by mocks
+pub struct Body(#[allow(dead_code)] Inner);
+#[allow(dead_code)]
+enum Inner {
+    Full(Vec<u8>),
+    Streamed(Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>),
+}
+impl Body {
+    pub(crate) fn from_reader<R>(reader: R, _len: Option<u64>) -> Self
+    where
+        R: futures::AsyncRead + Send + Unpin + 'static,
+    {
+        Self::from_stream(crate::body::ReaderStream::new(reader), None)
+    }
+    pub(crate) fn from_stream<S>(stream: S, _len: Option<u64>) -> Self
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        Body(Inner::Streamed(Box::pin(stream)))
+    }
+}
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Inner::Full(v) => f.debug_tuple("Body").field(v).finish(),
+            Inner::Streamed(_) => f.write_str("Body(streamed)"),
+        }
+    }
+}
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct HeaderName(pub(crate) String);
 #[derive(Debug, Clone)]
 pub struct HeaderValue(pub(crate) Vec<u8>);
 
-impl crate::response::Responses for () {
+/// There are no connections to reuse
+pub(crate) enum Connection {}
+impl crate::pool::Poolable for Connection {
+    fn is_closed(&self) -> bool {
+        match *self {}
+    }
+    fn is_ready(&self) -> bool {
+        match *self {}
+    }
+    fn share(&self) -> Option<Self> {
+        match *self {}
+    }
+}
+
+/// Response of the dummy backend. There never is one
+pub struct NoResp;
+impl crate::response::Responses for NoResp {
     fn status(&self) -> u16 {
         500
     }
@@ -18,13 +67,16 @@ impl crate::response::Responses for () {
         "not implemented"
     }
     async fn json<D: DeserializeOwned>(&mut self) -> Result<D, Error> {
-        Err(Error {})
+        Err(Error::NoBackend)
     }
     async fn bytes(&mut self) -> Result<Vec<u8>, Error> {
-        Err(Error {})
+        Err(Error::NoBackend)
     }
     async fn string(&mut self) -> Result<String, Error> {
-        Err(Error {})
+        Err(Error::NoBackend)
+    }
+    fn into_stream(self) -> futures::stream::BoxStream<'static, std::io::Result<bytes::Bytes>> {
+        Box::pin(futures::stream::empty())
     }
     fn get_header(&self, _name: HeaderName) -> Option<&HeaderValue> {
         None
@@ -39,12 +91,28 @@ impl crate::response::Responses for () {
 
 /// Backend specific error
 #[derive(Debug)]
-pub struct Error {}
+pub enum Error {
+    NoBackend,
+    #[cfg(feature = "mock")]
+    Json(serde_json::Error),
+    #[cfg(feature = "mock")]
+    Urlencoded(serde_urlencoded::ser::Error),
+    #[cfg(feature = "mock")]
+    InvalidQueryString(serde_qs::Error),
+}
 impl std::error::Error for Error {}
 use std::fmt;
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(ERR_MSG)
+        match self {
+            Error::NoBackend => f.write_str(ERR_MSG),
+            #[cfg(feature = "mock")]
+            Error::Json(i) => write!(f, "{}", i),
+            #[cfg(feature = "mock")]
+            Error::Urlencoded(i) => write!(f, "{}", i),
+            #[cfg(feature = "mock")]
+            Error::InvalidQueryString(i) => write!(f, "{}", i),
+        }
     }
 }
 impl From<Error> for crate::Error {
@@ -54,7 +122,7 @@ impl From<Error> for crate::Error {
 }
 
 pub use maybemock::{Req, Resp};
-#[cfg(not(all(feature = "mock_tests", test)))]
+#[cfg(not(feature = "mock"))]
 mod maybemock {
     use super::*;
     #[derive(Debug)]
@@ -82,10 +150,10 @@ mod maybemock {
             log::debug!("{} {}", meth, uri);
             Ok(Req {})
         }
-        async fn send_request(self) -> Result<crate::Response, Error> {
+        async fn send_request(self, _options: &Options) -> Result<Resp, Error> {
             eprintln!("{}", ERR_MSG);
             println!("{}", ERR_MSG);
-            Err(Error {})
+            Err(Error::NoBackend)
         }
         fn json<T: Serialize + ?Sized>(&mut self, _json: &T) -> Result<(), Error> {
             Ok(())
@@ -105,26 +173,43 @@ mod maybemock {
         fn add_header(&mut self, _name: HeaderName, _values: HeaderValue) -> Result<(), Error> {
             Ok(())
         }
+        fn set_pool(&mut self, _pool: crate::pool::Pool) {}
+        fn method(&self) -> String {
+            String::new()
+        }
+        fn url(&self) -> String {
+            String::new()
+        }
+        async fn try_clone(&mut self) -> Option<Self> {
+            None
+        }
+        fn set_url(&mut self, _url: &str) -> Result<(), Error> {
+            Ok(())
+        }
+        fn change_to_get(&mut self) {}
+        fn remove_header(&mut self, _name: &str) {}
     }
-    pub type Resp = ();
+    pub type Resp = NoResp;
 }
-#[cfg(all(feature = "mock_tests", test))]
+#[cfg(feature = "mock")]
 mod maybemock {
     use super::*;
     impl From<serde_json::Error> for Error {
         fn from(e: serde_json::Error) -> Self {
-            panic!("test with invalid json {}", e);
+            Self::Json(e)
         }
     }
     impl crate::mock::MockedRequest for Req {
-        /// on error, return full body
-        fn assert_body_bytes(&mut self, should_be: &[u8]) -> Result<(), Vec<u8>> {
-            let is = &self.body;
-            if is != should_be {
-                Err(is.clone())
-            } else {
-                Ok(())
+        async fn body_bytes(&mut self) -> std::io::Result<Vec<u8>> {
+            if let Inner::Streamed(stream) = &mut self.body.0 {
+                use futures::TryStreamExt;
+                let chunks: Vec<Bytes> = stream.try_collect().await?;
+                self.body = chunks.concat().into();
             }
+            let Inner::Full(is) = &self.body.0 else {
+                unreachable!()
+            };
+            Ok(is.clone())
         }
         fn get_headers(&self, name: &str) -> Option<Vec<crate::mock::MockHeaderValue>> {
             let name = HeaderName(name.to_string());
@@ -139,10 +224,10 @@ mod maybemock {
     pub struct Req {
         meth: String,
         uri: String,
-        body: Vec<u8>,
+        body: Body,
         header: std::collections::HashMap<HeaderName, Vec<HeaderValue>>,
     }
-    pub type Resp = crate::mock::Resp<()>;
+    pub type Resp = crate::mock::Resp<NoResp>;
     impl crate::request::Requests for Req {
         fn get(uri: &str) -> Req {
             Req::new("GET", uri).unwrap()
@@ -167,39 +252,43 @@ mod maybemock {
             Ok(Req {
                 meth: meth.to_ascii_uppercase(),
                 uri: uri.to_string(),
-                body: Default::default(),
+                body: Vec::new().into(),
                 header: Default::default(),
             })
         }
-        async fn send_request(self) -> Result<crate::Response, Error> {
+        async fn send_request(self, _options: &Options) -> Result<Resp, Error> {
             eprintln!("{}", ERR_MSG);
             println!("{}", ERR_MSG);
-            Err(Error {})
+            Err(Error::NoBackend)
         }
         fn json<T: Serialize + ?Sized>(&mut self, json: &T) -> Result<(), Error> {
-            let b = serde_json::to_string(json).unwrap();
+            let b = serde_json::to_string(json)?;
             self.set_header(
-                HeaderName("CONTENT_TYPE".to_string()),
+                HeaderName("content-type".to_string()),
                 HeaderValue(b"application/json".to_vec()),
             )?;
             self.body(b)
         }
         fn form<T: Serialize + ?Sized>(&mut self, data: &T) -> Result<(), Error> {
-            let b = serde_urlencoded::to_string(data).unwrap();
+            let b = serde_urlencoded::to_string(data).map_err(Error::Urlencoded)?;
             self.set_header(
-                HeaderName("CONTENT_TYPE".to_string()),
+                HeaderName("content-type".to_string()),
                 HeaderValue(b"application/x-www-form-urlencoded".to_vec()),
             )?;
             self.body(b)
         }
         fn query<T: Serialize + ?Sized>(&mut self, query: &T) -> Result<(), Error> {
-            let q = serde_qs::to_string(&query).unwrap();
-            todo!();
+            let q = serde_qs::to_string(&query).map_err(Error::InvalidQueryString)?;
+            //replaces the query, like the other backends
+            if let Some(i) = self.uri.find('?') {
+                self.uri.truncate(i);
+            }
+            self.uri.push('?');
+            self.uri.push_str(&q);
             Ok(())
         }
         fn body<B: Into<Body>>(&mut self, b: B) -> Result<(), Error> {
-            let b: Body = b.into();
-            self.body = b.0;
+            self.body = b.into();
             Ok(())
         }
         fn set_header(&mut self, name: HeaderName, values: HeaderValue) -> Result<(), Error> {
@@ -215,31 +304,64 @@ mod maybemock {
             }
             Ok(())
         }
+        fn set_pool(&mut self, _pool: crate::pool::Pool) {}
+        fn method(&self) -> String {
+            self.meth.clone()
+        }
+        fn url(&self) -> String {
+            self.uri.clone()
+        }
+        async fn try_clone(&mut self) -> Option<Self> {
+            //a streamed body can only be read once
+            let Inner::Full(body) = &self.body.0 else {
+                return None;
+            };
+            Some(Req {
+                meth: self.meth.clone(),
+                uri: self.uri.clone(),
+                body: body.clone().into(),
+                header: self.header.clone(),
+            })
+        }
+        fn set_url(&mut self, url: &str) -> Result<(), Error> {
+            self.uri = url.to_string();
+            Ok(())
+        }
+        fn change_to_get(&mut self) {
+            if self.meth != "HEAD" {
+                self.meth = "GET".to_string();
+            }
+            self.body = Vec::new().into();
+            self.remove_header("content-type");
+        }
+        fn remove_header(&mut self, name: &str) {
+            self.header.retain(|n, _| !n.0.eq_ignore_ascii_case(name));
+        }
     }
 }
 
 impl From<String> for Body {
     #[inline]
     fn from(t: String) -> Self {
-        Body(t.as_bytes().to_vec())
+        Body(Inner::Full(t.into_bytes()))
     }
 }
 impl From<Vec<u8>> for Body {
     #[inline]
     fn from(t: Vec<u8>) -> Self {
-        Body(t)
+        Body(Inner::Full(t))
     }
 }
 impl From<&'static [u8]> for Body {
     #[inline]
     fn from(t: &'static [u8]) -> Self {
-        Body(t.to_vec())
+        Body(Inner::Full(t.to_vec()))
     }
 }
 impl From<&'static str> for Body {
     #[inline]
     fn from(t: &'static str) -> Self {
-        Body(t.as_bytes().to_vec())
+        Body(Inner::Full(t.as_bytes().to_vec()))
     }
 }
 impl<'a> TryFrom<&'a str> for HeaderValue {
@@ -1,13 +1,19 @@
-use crate::tcp::Stream;
+use crate::{
+    pool::{ConnKey, Pool, Poolable},
+    request::Options,
+    tcp::{Forward, Stream},
+    timeout::{timeout, TimeoutKind},
+};
 #[cfg(feature = "http2")]
 use hyper::client::conn::http2;
 use hyper::{
     client::conn::http1,
-    header::{HeaderValue, HOST},
+    header::{HeaderValue, HOST, PROXY_AUTHORIZATION},
     http::uri::{Scheme, Uri},
 };
 
-async fn connect_to_uri(dst: &Uri) -> Result<Stream, super::Error> {
+/// what to connect to: is https, host, port
+pub(crate) fn conn_key(dst: &Uri) -> Result<ConnKey, super::Error> {
     let tls = match dst.scheme_str() {
         Some("https") => true,
         Some("http") => false,
@@ -31,108 +37,288 @@ async fn connect_to_uri(dst: &Uri) -> Result<Stream, super::Error> {
             }
         }
     };
-    Stream::connect(host, port, tls).await.map_err(|e| e.into())
+    Ok((tls, host.to_string(), port))
+}
+
+async fn connect_to_uri(dst: &Uri, options: &Options) -> Result<Stream, super::Error> {
+    let (tls, host, port) = conn_key(dst)?;
+    Stream::connect(&host, port, tls, options)
+        .await
+        .map_err(|e| e.into())
 }
 
 #[derive(Debug, Default)]
 pub enum HyperClient {
     #[default]
     New,
-    H1(http1::SendRequest<super::Body>),
+    /// Requests are sent in absolute-form, if `Forward` is set
+    H1(http1::SendRequest<super::Body>, Option<Forward>),
     #[cfg(feature = "http2")]
     H2(http2::SendRequest<super::Body>),
-}
-
-#[derive(Clone)]
-#[cfg(feature = "http2")]
-struct TokioExecutor;
-#[cfg(feature = "http2")]
-impl<F> hyper::rt::Executor<F> for TokioExecutor
-where
-    F: std::future::Future + Send + 'static,
-    F::Output: Send + 'static,
-{
-    fn execute(&self, future: F) {
-        tokio::spawn(future);
-    }
+    /// Never `New`, as it connects to another port
+    #[cfg(feature = "http3")]
+    H3(super::http3::Client),
 }
 
 impl HyperClient {
     pub async fn request(
         &mut self,
         mut req: super::Request<super::Body>,
+        options: &Options,
+        pool: &Pool,
     ) -> Result<super::Response<super::Incoming>, super::Error> {
         match self {
-            HyperClient::New => {
-                let io = connect_to_uri(req.uri()).await?;
-                match io.get_proto() {
-                    #[cfg(feature = "http2")]
-                    hyper::Version::HTTP_2 => {
-                        let (sender, conn) =
-                            hyper::client::conn::http2::handshake(TokioExecutor, io).await?;
-                        tokio::task::spawn(async move {
-                            if let Err(err) = conn.await {
-                                println!("Connection failed: {:?}", err);
-                            }
-                        });
-                        let _ = std::mem::replace(self, HyperClient::H2(sender));
-                    }
-                    hyper::Version::HTTP_11 => {
-                        let (sender, conn) = hyper::client::conn::http1::handshake(io).await?;
-                        tokio::task::spawn(async move {
-                            if let Err(err) = conn.await {
-                                println!("Connection failed: {:?}", err);
-                            }
-                        });
-                        let _ = std::mem::replace(self, HyperClient::H1(sender));
-                    }
-                    _ => unreachable!(),
-                };
+            //the server might have closed the connection or the last response is still being read
+            HyperClient::H1(sender, _) if sender.is_closed() || !sender.is_ready() => {
+                *self = HyperClient::New
             }
-            HyperClient::H1(_) => {}
             #[cfg(feature = "http2")]
-            HyperClient::H2(_) => {}
+            HyperClient::H2(sender) if sender.is_closed() => *self = HyperClient::New,
+            _ => {}
         }
+        let uri = req.uri().clone();
+        loop {
+            let reused = !matches!(self, HyperClient::New);
+            if !reused {
+                self.connect(&uri, options, pool).await?;
+            }
+            let sent = match self {
+                HyperClient::New => unreachable!(),
+                #[cfg(feature = "http3")]
+                HyperClient::H3(_) => unreachable!("sent by http3::Client"),
+                HyperClient::H1(sender, forward) => {
+                    let (mut parts, body) = req.into_parts();
+                    let auth = uri.authority().expect("authority implies host");
+                    parts.headers.entry(HOST).or_insert_with(|| {
+                        let hostname = auth.host();
+                        if let Some(port) = auth.port() {
+                            let s = format!("{}:{}", hostname, port);
+                            HeaderValue::from_str(&s)
+                        } else {
+                            HeaderValue::from_str(hostname)
+                        }
+                        .expect("uri host is valid header value")
+                    });
 
-        
-        match self {
-            HyperClient::New => unreachable!(),
-            HyperClient::H1(sender) => {
-
-                let (mut parts, body) = req.into_parts();
-                let mut up = parts.uri.into_parts();
-
-                let auth = up.authority.take().expect("authority implies host");
-                parts.headers.entry(HOST).or_insert_with(|| {
-                    let hostname = auth.host();
-                    if let Some(port) = auth.port() {
-                        let s = format!("{}:{}", hostname, port);
-                        HeaderValue::from_str(&s)
+                    if let Some(forward) = forward {
+                        //absolute_form - the proxy needs to know where to go
+                        parts.uri = uri.clone();
+                        if let Some(authorization) = &forward.authorization {
+                            let mut v = HeaderValue::from_str(authorization)
+                                .expect("base64 is valid header value");
+                            v.set_sensitive(true);
+                            parts.headers.entry(PROXY_AUTHORIZATION).or_insert(v);
+                        }
                     } else {
-                        HeaderValue::from_str(hostname)
+                        //origin_form
+                        parts.uri = match uri.path_and_query() {
+                            Some(path) if path.as_str() != "/" => {
+                                let mut parts = hyper::http::uri::Parts::default();
+                                parts.path_and_query = Some(path.clone());
+                                Uri::from_parts(parts).expect("path is valid uri")
+                            }
+                            _none_or_just_slash => {
+                                debug_assert!(Uri::default() == "/");
+                                Uri::default()
+                            }
+                        };
                     }
-                    .expect("uri host is valid header value")
-                });
 
-                //origin_form
-                parts.uri = match up.path_and_query {
-                    Some(path) if path.as_str() != "/" => {
-                        let mut parts = hyper::http::uri::Parts::default();
-                        parts.path_and_query = Some(path);
-                        Uri::from_parts(parts).expect("path is valid uri")
+                    let req = hyper::Request::from_parts(parts, body);
+                    let resp = sender.try_send_request(req);
+                    timeout(options.timeouts.first_byte, TimeoutKind::FirstByte, resp).await?
+                }
+                #[cfg(feature = "http2")]
+                HyperClient::H2(sender) => {
+                    let resp = sender.try_send_request(req);
+                    timeout(options.timeouts.first_byte, TimeoutKind::FirstByte, resp).await?
+                }
+            };
+            match sent {
+                Ok(resp) => return Ok(resp),
+                Err(mut e) => match e.take_message() {
+                    //the connection was closed before the request was sent
+                    Some(unsent) if reused => {
+                        log::debug!("pooled connection is gone, retrying on a new one");
+                        req = unsent;
+                        *self = HyperClient::New;
                     }
-                    _none_or_just_slash => {
-                        debug_assert!(Uri::default() == "/");
-                        Uri::default()
+                    _ => return Err(e.into_error().into()),
+                },
+            }
+        }
+    }
+    /// Open a new connection to `uri`, once `pool` allows it
+    async fn connect(
+        &mut self,
+        uri: &Uri,
+        options: &Options,
+        pool: &Pool,
+    ) -> Result<(), super::Error> {
+        let permit = pool.permit().await;
+        let io = connect_to_uri(uri, options).await?;
+        let version = match io.get_proto() {
+            //h2c. A forwarding proxy only speaks HTTP/1.1
+            #[cfg(feature = "http2")]
+            hyper::Version::HTTP_11
+                if options.http2_prior_knowledge
+                    && uri.scheme() == Some(&Scheme::HTTP)
+                    && io.forward().is_none() =>
+            {
+                hyper::Version::HTTP_2
+            }
+            v => v,
+        };
+        match version {
+            #[cfg(feature = "http2")]
+            hyper::Version::HTTP_2 => {
+                let (sender, conn) = options.http2.builder().handshake(io).await?;
+                let authority = authority(uri);
+                tokio::task::spawn(async move {
+                    let _permit = permit;
+                    if let Err(err) = conn.await {
+                        log::warn!("HTTP/2 connection to {} failed: {}", authority, err);
                     }
-                };
+                });
+                *self = HyperClient::H2(sender);
+            }
+            hyper::Version::HTTP_11 => {
+                let forward = io.forward().cloned();
+                let (sender, conn) = hyper::client::conn::http1::handshake(io).await?;
+                let authority = authority(uri);
+                tokio::task::spawn(async move {
+                    let _permit = permit;
+                    if let Err(err) = conn.await {
+                        log::warn!("connection to {} failed: {}", authority, err);
+                    }
+                });
+                *self = HyperClient::H1(sender, forward);
+            }
+            _ => unreachable!(),
+        };
+        Ok(())
+    }
+}
+/// host and port of `uri`, for logging
+fn authority(uri: &Uri) -> String {
+    uri.authority().map(|a| a.to_string()).unwrap_or_default()
+}
+impl Poolable for HyperClient {
+    fn is_closed(&self) -> bool {
+        match self {
+            HyperClient::New => true,
+            HyperClient::H1(sender, _) => sender.is_closed(),
+            #[cfg(feature = "http2")]
+            HyperClient::H2(sender) => sender.is_closed(),
+            #[cfg(feature = "http3")]
+            HyperClient::H3(client) => client.is_closed(),
+        }
+    }
+    fn is_ready(&self) -> bool {
+        match self {
+            HyperClient::New => false,
+            HyperClient::H1(sender, _) => sender.is_ready(),
+            #[cfg(feature = "http2")]
+            HyperClient::H2(sender) => sender.is_ready(),
+            #[cfg(feature = "http3")]
+            HyperClient::H3(_) => true,
+        }
+    }
+    fn share(&self) -> Option<Self> {
+        match self {
+            #[cfg(feature = "http2")]
+            HyperClient::H2(sender) => Some(HyperClient::H2(sender.clone())),
+            #[cfg(feature = "http3")]
+            HyperClient::H3(client) => Some(HyperClient::H3(client.clone())),
+            _ => None,
+        }
+    }
+}
 
-                let req = hyper::Request::from_parts(parts, body);
+#[cfg(all(test, feature = "http2"))]
+mod tests {
+    use crate::pool::{Pool, PoolConfig};
+    use crate::tests::{block_on, listen_somewhere, spawn, TcpListener};
+    use crate::Request;
+    use std::io;
 
-                sender.send_request(req).await.map_err(|e| e.into())
-            },
-            #[cfg(feature = "http2")]
-            HyperClient::H2(sender) => sender.send_request(req).await.map_err(|e| e.into()),
+    /// answer two requests on one connection
+    async fn server(listener: TcpListener) -> io::Result<bool> {
+        let (stream, _) = listener.accept().await?;
+        let mut conn = h2::server::handshake(stream)
+            .await
+            .map_err(io::Error::other)?;
+        for _ in 0..2 {
+            let (req, mut respond) = conn
+                .accept()
+                .await
+                .expect("a request")
+                .map_err(io::Error::other)?;
+            assert_eq!(req.uri().path(), "/h2c");
+            let mut body = respond
+                .send_response(hyper::Response::new(()), false)
+                .map_err(io::Error::other)?;
+            body.send_data(bytes::Bytes::from_static(b"abc"), true)
+                .map_err(io::Error::other)?;
         }
+        conn.graceful_shutdown();
+        std::future::poll_fn(|cx| conn.poll_closed(cx))
+            .await
+            .map_err(io::Error::other)?;
+        Ok(true)
+    }
+    #[test]
+    fn prior_knowledge() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+            let pool = Pool::new(PoolConfig::new());
+            let uri = format!("http://{}:{}/h2c", host, port);
+            //not through a proxy from the environment
+            #[cfg(feature = "proxies")]
+            let no_proxy = std::sync::Arc::new(crate::proxy::NoProxy);
+            for _ in 0..2 {
+                let req = Request::get(&uri).http2_prior_knowledge().pool(&pool);
+                #[cfg(feature = "proxies")]
+                let req = req.proxy(no_proxy.clone());
+                let mut aw = req.exec().await?;
+                assert_eq!(aw.status_code(), 200);
+                assert_eq!(aw.text().await?, "abc", "wrong text");
+            }
+            let stats = pool.stats();
+            assert_eq!((stats.hits, stats.misses), (1, 1));
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn keep_alive_timeout() {
+        use crate::tests::ReadExt;
+        use crate::{Error, Http2Config};
+        use std::time::Duration;
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            //never answers, not even a PING
+            let t = spawn(async move {
+                let (mut stream, _) = listener.accept().await?;
+                let _ = stream.read_to_end(&mut Vec::new()).await;
+                io::Result::Ok(true)
+            });
+            let h2 = Http2Config::new()
+                .keep_alive_interval(Duration::from_millis(50))
+                .keep_alive_timeout(Duration::from_millis(50));
+            let req = Request::get(&format!("http://{}:{}/", host, port))
+                .http2_prior_knowledge()
+                .http2(h2)
+                .timeout(Duration::from_secs(5));
+            #[cfg(feature = "proxies")]
+            let req = req.proxy(std::sync::Arc::new(crate::proxy::NoProxy));
+            let e = req.exec().await.unwrap_err();
+            assert!(!matches!(e, Error::Timeout(_)), "{:?}", e);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
     }
 }
@@ -0,0 +1,154 @@
+use hyper::client::conn::http2::Builder;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Settings for HTTP/2 connections.
+/// Attach it with [`Request::http2`](crate::Request::http2) or [`Session::http2`](crate::Session::http2).
+///
+/// Everything not set is left to the defaults of hyper.
+/// Connections are only reused by requests with the same settings.
+/// ```
+/// # use generic_async_http_client::{Request, Http2Config, Error};
+/// # use std::time::Duration;
+/// # async fn get() -> Result<(), Error> {
+///     let h2 = Http2Config::new()
+///         .adaptive_window(true)
+///         .keep_alive_interval(Duration::from_secs(30))
+///         .keep_alive_timeout(Duration::from_secs(10))
+///         .keep_alive_while_idle(true);
+///     Request::get("https://example.com/").http2(h2).exec().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Http2Config {
+    initial_stream_window_size: Option<u32>,
+    initial_connection_window_size: Option<u32>,
+    adaptive_window: bool,
+    max_frame_size: Option<u32>,
+    initial_max_send_streams: Option<usize>,
+    max_concurrent_streams: Option<u32>,
+    max_header_list_size: Option<u32>,
+    keep_alive_interval: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    keep_alive_while_idle: bool,
+}
+impl Http2Config {
+    pub fn new() -> Http2Config {
+        Http2Config::default()
+    }
+    /// Flow control window of each stream, in bytes
+    pub fn initial_stream_window_size(mut self, size: u32) -> Self {
+        self.initial_stream_window_size = Some(size);
+        self
+    }
+    /// Flow control window of the whole connection, in bytes
+    pub fn initial_connection_window_size(mut self, size: u32) -> Self {
+        self.initial_connection_window_size = Some(size);
+        self
+    }
+    /// Grow the windows based on the measured bandwidth-delay product.
+    /// Overrides both initial window sizes
+    pub fn adaptive_window(mut self, enabled: bool) -> Self {
+        self.adaptive_window = enabled;
+        self
+    }
+    /// Largest frame the server may send, in bytes
+    pub fn max_frame_size(mut self, size: u32) -> Self {
+        self.max_frame_size = Some(size);
+        self
+    }
+    /// Requests that are sent at once, until the server tells its own limit
+    pub fn initial_max_send_streams(mut self, max: usize) -> Self {
+        self.initial_max_send_streams = Some(max);
+        self
+    }
+    /// Streams the server may open at once (server push)
+    pub fn max_concurrent_streams(mut self, max: u32) -> Self {
+        self.max_concurrent_streams = Some(max);
+        self
+    }
+    /// Largest header list of a response, in bytes
+    pub fn max_header_list_size(mut self, size: u32) -> Self {
+        self.max_header_list_size = Some(size);
+        self
+    }
+    /// Send a PING every `interval`. By default, none are sent
+    pub fn keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.keep_alive_interval = Some(interval);
+        self
+    }
+    /// Close the connection, if a PING is not answered within `timeout`
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+    /// Also send PINGs while no request is in flight.
+    /// Keeps pooled connections from being dropped by load balancers
+    pub fn keep_alive_while_idle(mut self, enabled: bool) -> Self {
+        self.keep_alive_while_idle = enabled;
+        self
+    }
+    pub(crate) fn builder(&self) -> Builder<TokioExecutor> {
+        let mut b = Builder::new(TokioExecutor);
+        b.timer(TokioTimer)
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size)
+            .initial_max_send_streams(self.initial_max_send_streams)
+            .max_concurrent_streams(self.max_concurrent_streams)
+            .keep_alive_interval(self.keep_alive_interval)
+            .keep_alive_while_idle(self.keep_alive_while_idle);
+        //after the window sizes, as they turn it off
+        if self.adaptive_window {
+            b.adaptive_window(true);
+        }
+        if let Some(size) = self.max_frame_size {
+            b.max_frame_size(size);
+        }
+        if let Some(size) = self.max_header_list_size {
+            b.max_header_list_size(size);
+        }
+        if let Some(timeout) = self.keep_alive_timeout {
+            b.keep_alive_timeout(timeout);
+        }
+        b
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct TokioExecutor;
+impl<F> hyper::rt::Executor<F> for TokioExecutor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, future: F) {
+        tokio::spawn(future);
+    }
+}
+
+/// Lets hyper sleep, for keep-alive
+#[derive(Clone)]
+struct TokioTimer;
+impl hyper::rt::Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn hyper::rt::Sleep>> {
+        Box::pin(TokioSleep(Box::pin(tokio::time::sleep(duration))))
+    }
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn hyper::rt::Sleep>> {
+        Box::pin(TokioSleep(Box::pin(tokio::time::sleep_until(
+            deadline.into(),
+        ))))
+    }
+}
+struct TokioSleep(Pin<Box<tokio::time::Sleep>>);
+impl Future for TokioSleep {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
+impl hyper::rt::Sleep for TokioSleep {}
@@ -0,0 +1,507 @@
+use super::{Body, Error, HyperClient, RespBody};
+use crate::{
+    pool::{ConnKey, Pool, PoolKey},
+    request::Options,
+    tcp::resolve,
+    timeout::{timeout, TimeoutKind},
+    TlsConfig,
+};
+use bytes::Bytes;
+use hyper::{body::Body as _, header::ALT_SVC, HeaderMap, Request, Response};
+use std::{
+    future::poll_fn,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    time::Duration,
+};
+
+/// When to use HTTP/3 (over QUIC).
+/// Set it with [`Request::http3`](crate::Request::http3) or [`Session::http3`](crate::Session::http3).
+///
+/// HTTP/3 is only used for `https://`.
+/// QUIC can not go through a proxy, so it is only used for hosts the proxy does not apply to
+/// (see [`Proxy::is_direct`](crate::proxy::Proxy::is_direct))
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Http3 {
+    /// Never (the default)
+    #[default]
+    Off,
+    /// Once the server announced it with an `Alt-Svc` header.
+    /// Until then, and if the QUIC connection fails, HTTP/1.1 or HTTP/2 is used
+    AltSvc,
+    /// Always. Fails if the server does not speak HTTP/3
+    Only,
+}
+
+type Sender = h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>;
+/// A request in flight. The response body is read from it
+pub(crate) type Stream = h3::client::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>;
+
+/// An HTTP/3 connection. Clones share it
+#[derive(Clone)]
+pub(crate) struct Client {
+    sender: Sender,
+    conn: quinn::Connection,
+}
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("H3")
+            .field(&self.conn.remote_address())
+            .finish()
+    }
+}
+
+/// The HTTP/3 connection for a request to `origin`, if HTTP/3 should be used.
+/// A new one is opened, if `pool` has none
+pub(crate) async fn client(
+    origin: &ConnKey,
+    key: &PoolKey,
+    options: &Options,
+    pool: &Pool,
+) -> Result<Option<(PoolKey, Client)>, Error> {
+    let port = match options.http3 {
+        Http3::Off => return Ok(None),
+        Http3::Only if !origin.0 => {
+            return Err(unsupported("HTTP/3 is only used for https"));
+        }
+        Http3::Only => origin.2,
+        Http3::AltSvc => match pool.alt_svc(origin) {
+            Some(port) if origin.0 => port,
+            _ => return Ok(None),
+        },
+    };
+    #[cfg(unix)]
+    if options.unix_socket.is_some() {
+        if options.http3 == Http3::AltSvc {
+            return Ok(None);
+        }
+        return Err(unsupported("HTTP/3 over unix sockets is not supported"));
+    }
+    if !direct(origin, options) {
+        if options.http3 == Http3::AltSvc {
+            return Ok(None);
+        }
+        return Err(unsupported("HTTP/3 can not go through a proxy"));
+    }
+    let key = key.clone().quic(port);
+    if let Some(HyperClient::H3(client)) = pool.take(&key) {
+        return Ok(Some((key, client)));
+    }
+    match Client::connect(&origin.1, port, options, pool).await {
+        Ok(client) => Ok(Some((key, client))),
+        Err(e) if options.http3 == Http3::AltSvc => {
+            log::debug!(
+                "HTTP/3 to {}:{} failed, falling back to TCP: {}",
+                origin.1,
+                port,
+                e
+            );
+            pool.set_alt_svc(origin, None);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+fn unsupported(msg: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg).into()
+}
+/// If `origin` is connected to without a proxy
+#[cfg_attr(not(feature = "proxies"), allow(unused_variables))]
+fn direct(origin: &ConnKey, options: &Options) -> bool {
+    #[cfg(feature = "proxies")]
+    {
+        let (_, host, port) = origin;
+        let prx = match &options.proxy {
+            Some(scoped) => &*scoped.0,
+            None => crate::proxy::proxy(),
+        };
+        prx.is_direct(host, *port)
+            && prx.https_proxy(host, *port, true).is_none()
+            && prx.forward_proxy(host, *port).is_none()
+    }
+    #[cfg(not(feature = "proxies"))]
+    true
+}
+
+impl Client {
+    /// Open a QUIC connection to `host`, once `pool` allows it
+    async fn connect(
+        host: &str,
+        port: u16,
+        options: &Options,
+        pool: &Pool,
+    ) -> Result<Client, Error> {
+        let permit = pool.permit().await;
+        let config = options
+            .tls
+            .as_ref()
+            .unwrap_or_else(|| TlsConfig::default_ref())
+            .quic_config()?;
+        let resolver = resolve::Filtered::of(options);
+        let server_name = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host);
+        let conn = async {
+            let mut last = None;
+            for addr in resolve::lookup(&resolver, host, port).await? {
+                match connect_addr(addr, server_name, config.clone()).await {
+                    Ok(conn) => return Ok(conn),
+                    Err(e) => {
+                        log::debug!("QUIC to {} failed: {}", addr, e);
+                        last = Some(e);
+                    }
+                }
+            }
+            Err(last.expect("lookup is never empty"))
+        };
+        let conn = timeout(options.timeouts.connect, TimeoutKind::Connect, conn).await??;
+        log::trace!("connected to {}:{} with QUIC", host, port);
+
+        let (mut driver, sender) = h3::client::new(h3_quinn::Connection::new(conn.clone()))
+            .await
+            .map_err(Error::H3Connection)?;
+        let authority = format!("{}:{}", host, port);
+        tokio::task::spawn(async move {
+            let _permit = permit;
+            let err = poll_fn(|cx| driver.poll_close(cx)).await;
+            if !err.is_h3_no_error() {
+                log::warn!("HTTP/3 connection to {} failed: {}", authority, err);
+            }
+        });
+        Ok(Client { sender, conn })
+    }
+    pub(crate) async fn request(
+        &mut self,
+        req: Request<Body>,
+        options: &Options,
+    ) -> Result<Response<RespBody>, Error> {
+        let resp = async {
+            let (parts, mut body) = req.into_parts();
+            let mut stream = self
+                .sender
+                .send_request(Request::from_parts(parts, ()))
+                .await?;
+            while let Some(frame) = poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+                if let Ok(data) = frame?.into_data() {
+                    stream.send_data(data).await?;
+                }
+            }
+            stream.finish().await?;
+            let resp = stream.recv_response().await?;
+            Ok::<_, Error>(resp.map(|()| RespBody::H3(Box::new(stream))))
+        };
+        timeout(options.timeouts.first_byte, TimeoutKind::FirstByte, resp).await?
+    }
+    pub(crate) fn is_closed(&self) -> bool {
+        self.conn.close_reason().is_some()
+    }
+}
+/// QUIC from a new UDP socket to `addr`
+async fn connect_addr(
+    addr: SocketAddr,
+    server_name: &str,
+    config: quinn::ClientConfig,
+) -> io::Result<quinn::Connection> {
+    let local: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let endpoint = quinn::Endpoint::client(local)?;
+    let connecting = endpoint
+        .connect_with(config, addr, server_name)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(connecting.await?)
+}
+
+/// Learn from the `Alt-Svc` header of a response from `origin`, if HTTP/3 is used on announcement.
+/// Only alternatives on the same host are followed
+pub(crate) fn learn(pool: &Pool, origin: &ConnKey, options: &Options, headers: &HeaderMap) {
+    if options.http3 != Http3::AltSvc || !origin.0 || !headers.contains_key(ALT_SVC) {
+        return;
+    }
+    //it might be the proxy that offers it
+    if !direct(origin, options) {
+        return;
+    }
+    //a new header replaces all alternatives
+    let alt = headers
+        .get_all(ALT_SVC)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find_map(parse_alt_svc);
+    log::trace!("{}:{} offers HTTP/3 {:?}", origin.1, origin.2, alt);
+    pool.set_alt_svc(origin, alt);
+}
+/// UDP port and max age of `h3` in an `Alt-Svc` value
+fn parse_alt_svc(value: &str) -> Option<(u16, Duration)> {
+    for alternative in value.split(',') {
+        let mut params = alternative.split(';').map(str::trim);
+        let Some(("h3", authority)) = params.next().and_then(|p| p.split_once('=')) else {
+            continue;
+        };
+        //only `:port`, not an other host
+        let Some(Ok(port)) = authority
+            .trim_matches('"')
+            .strip_prefix(':')
+            .map(str::parse::<u16>)
+        else {
+            continue;
+        };
+        let max_age = params
+            .filter_map(|p| p.strip_prefix("ma="))
+            .find_map(|s| s.trim_matches('"').parse().ok())
+            .unwrap_or(24 * 60 * 60);
+        return Some((port, Duration::from_secs(max_age)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::PoolConfig;
+    use crate::tests::{block_on, spawn, TcpListener, WriteExt};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use std::sync::Arc;
+    use tokio_rustls::rustls::{self, pki_types::PrivatePkcs8KeyDer};
+
+    #[test]
+    fn alt_svc_header() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(parse_alt_svc("h3=\":443\""), Some((443, day)));
+        assert_eq!(
+            parse_alt_svc("h2=\":443\"; ma=60, h3=\":8443\"; ma=3600; persist=1"),
+            Some((8443, Duration::from_secs(3600)))
+        );
+        assert_eq!(parse_alt_svc("h3=\"other.example:443\""), None);
+        assert_eq!(parse_alt_svc("clear"), None);
+    }
+
+    /// TLS config of a server for `localhost` and a client that trusts it
+    fn certs() -> (rustls::ServerConfig, TlsConfig) {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca = CertificateParams::new(Vec::new()).unwrap();
+        ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca.self_signed(&ca_key).unwrap();
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&key, &ca, &ca_key)
+            .unwrap();
+        let mut server = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.der().clone()],
+                PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+            )
+            .unwrap();
+        server.alpn_protocols = vec![b"h3".to_vec()];
+        let client = TlsConfig::new()
+            .add_root_der(ca.der())
+            .unwrap()
+            .built_in_roots(false);
+        (server, client)
+    }
+    /// HTTP/3 server on `port` of 127.0.0.1 (or any port). Answers `n` requests on one connection
+    fn quic_server(
+        tls: rustls::ServerConfig,
+        port: u16,
+        n: usize,
+    ) -> io::Result<(
+        quinn::Endpoint,
+        impl std::future::Future<Output = io::Result<bool>>,
+    )> {
+        let crypto =
+            quinn::crypto::rustls::QuicServerConfig::try_from(tls).map_err(io::Error::other)?;
+        let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        let endpoint = quinn::Endpoint::server(config, (Ipv4Addr::LOCALHOST, port).into())?;
+        let ep = endpoint.clone();
+        let serve = async move {
+            let conn = ep.accept().await.expect("a connection").await?;
+            let mut conn = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn))
+                .await
+                .map_err(io::Error::other)?;
+            for _ in 0..n {
+                let resolver = conn
+                    .accept()
+                    .await
+                    .map_err(io::Error::other)?
+                    .expect("a request");
+                let (req, mut stream) =
+                    resolver.resolve_request().await.map_err(io::Error::other)?;
+                assert_eq!(req.uri().path(), "/h3");
+                stream
+                    .send_response(Response::new(()))
+                    .await
+                    .map_err(io::Error::other)?;
+                stream
+                    .send_data(Bytes::from_static(b"abc"))
+                    .await
+                    .map_err(io::Error::other)?;
+                stream.finish().await.map_err(io::Error::other)?;
+            }
+            //until the client is gone
+            let _ = conn.accept().await;
+            Ok(true)
+        };
+        Ok((endpoint, serve))
+    }
+    #[test]
+    fn only() {
+        block_on(async {
+            let (server, tls) = certs();
+            let (endpoint, serve) = quic_server(server, 0, 2)?;
+            let port = endpoint.local_addr()?.port();
+            let t = spawn(serve);
+            let pool = Pool::new(PoolConfig::new());
+            let uri = format!("https://localhost:{}/h3", port);
+            for _ in 0..2 {
+                let req = crate::Request::get(&uri)
+                    .http3(Http3::Only)
+                    .tls(&tls)
+                    .pool(&pool);
+                let mut aw = req.exec().await?;
+                assert_eq!(aw.status_code(), 200);
+                assert_eq!(aw.text().await?, "abc", "wrong text");
+            }
+            let stats = pool.stats();
+            assert_eq!((stats.hits, stats.misses), (1, 1));
+            pool.clear();
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn only_https() {
+        block_on(async {
+            let e = crate::Request::get("http://localhost/")
+                .http3(Http3::Only)
+                .exec()
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(&e, crate::Error::Io(e) if e.kind() == io::ErrorKind::InvalidInput),
+                "{:?}",
+                e
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+    /// The first response over TCP announces HTTP/3 on the same port
+    #[test]
+    fn learn_from_alt_svc() {
+        block_on(async {
+            let (mut server, tls) = certs();
+            let tcp = TcpListener::bind("127.0.0.1:0").await?;
+            let port = tcp.local_addr()?.port();
+            let (_endpoint, serve) = quic_server(server.clone(), port, 1)?;
+            let h3 = spawn(serve);
+            server.alpn_protocols = vec![b"http/1.1".to_vec()];
+            let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server));
+            let h1 = spawn(async move {
+                let (stream, _) = tcp.accept().await?;
+                let mut stream = acceptor.accept(stream).await?;
+                crate::tests::read_head(&mut stream).await?;
+                let resp = format!(
+                    "HTTP/1.1 200 OK\r\nalt-svc: h3=\":{}\"; ma=60\r\ncontent-length: 3\r\n\r\ntcp",
+                    port
+                );
+                stream.write_all(resp.as_bytes()).await?;
+                stream.flush().await?;
+                io::Result::Ok(true)
+            });
+            let pool = Pool::new(PoolConfig::new());
+            let uri = format!("https://localhost:{}/h3", port);
+            #[cfg(feature = "proxies")]
+            let no_proxy = Arc::new(crate::proxy::NoProxy);
+            let mut texts = Vec::new();
+            for _ in 0..2 {
+                let req = crate::Request::get(&uri)
+                    .http3(Http3::AltSvc)
+                    .tls(&tls)
+                    .pool(&pool);
+                #[cfg(feature = "proxies")]
+                let req = req.proxy(no_proxy.clone());
+                texts.push(req.exec().await?.text().await?);
+            }
+            assert_eq!(texts, ["tcp", "abc"]);
+            assert!(h1.await?, "not cool");
+            pool.clear();
+            assert!(h3.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    /// Alt-Svc is ignored if the origin is reached via a proxy
+    #[cfg(feature = "proxies")]
+    #[test]
+    fn not_past_proxy() {
+        struct Tunnel;
+        #[async_trait::async_trait]
+        impl crate::proxy::Proxy for Tunnel {
+            async fn connect_transport(
+                &self,
+                _host: &str,
+                _port: u16,
+                _tls: bool,
+                _resolver: &dyn crate::resolve::Resolve,
+            ) -> io::Result<Box<dyn crate::proxy::Transport>> {
+                Err(io::ErrorKind::ConnectionRefused.into())
+            }
+        }
+        block_on(async {
+            let pool = Pool::new(PoolConfig::new());
+            let origin: ConnKey = (true, "localhost".to_string(), 443);
+            let mut options = Options {
+                http3: Http3::AltSvc,
+                proxy: Some(crate::tcp::proxy::Scoped(Arc::new(Tunnel))),
+                ..Default::default()
+            };
+            let mut headers = HeaderMap::new();
+            headers.insert(ALT_SVC, "h3=\":443\"; ma=60".parse().unwrap());
+            learn(&pool, &origin, &options, &headers);
+            assert_eq!(pool.alt_svc(&origin), None, "learned from proxy");
+
+            pool.set_alt_svc(&origin, Some((443, Duration::from_secs(60))));
+            let key = PoolKey::new(origin.clone(), &options);
+            assert!(client(&origin, &key, &options, &pool).await?.is_none());
+
+            options.http3 = Http3::Only;
+            let e = match client(&origin, &key, &options, &pool).await {
+                Ok(_) => panic!("HTTP/3 past the proxy"),
+                Err(e) => e,
+            };
+            assert!(
+                matches!(&e, Error::Io(e) if e.kind() == io::ErrorKind::InvalidInput),
+                "{:?}",
+                e
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+    /// If the announced port does not answer, TCP is used again
+    #[test]
+    fn fall_back() {
+        block_on(async {
+            let pool = Pool::new(PoolConfig::new());
+            let origin: ConnKey = (true, "localhost".to_string(), 443);
+            pool.set_alt_svc(&origin, Some((1, Duration::from_secs(60))));
+            let mut options = Options {
+                http3: Http3::AltSvc,
+                ..Default::default()
+            };
+            options.timeouts.connect = Some(Duration::from_millis(200));
+            let key = PoolKey::new(origin.clone(), &options);
+            assert!(client(&origin, &key, &options, &pool).await?.is_none());
+            assert_eq!(pool.alt_svc(&origin), None, "not forgotten");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -1,6 +1,10 @@
+use futures::{AsyncRead, Stream};
 use std::{
     convert::{Infallible, TryFrom},
+    io,
+    pin::Pin,
     str::FromStr,
+    task::Poll,
 };
 
 use serde::Serialize;
@@ -11,7 +15,10 @@ pub use hyper::{
 };
 use hyper::{
     body::{Body as BodyTrait, Bytes, Frame, SizeHint},
-    header::{InvalidHeaderName, InvalidHeaderValue, CONTENT_TYPE},
+    header::{
+        InvalidHeaderName, InvalidHeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
+        TRANSFER_ENCODING,
+    },
     http::{
         method::{InvalidMethod, Method},
         request::Builder,
@@ -23,17 +30,31 @@ use hyper::{
 use std::mem::take;
 
 mod connector;
+#[cfg(feature = "http2")]
+mod http2;
+#[cfg(feature = "http3")]
+mod http3;
+use crate::{
+    body::ReaderStream,
+    pool::{Pool, PoolKey},
+    request::Options,
+    timeout::{timeout, TimeoutKind},
+};
 pub(crate) use connector::HyperClient;
+#[cfg(feature = "http2")]
+pub use http2::Http2Config;
+#[cfg(feature = "http3")]
+pub use http3::Http3;
+use std::time::Duration;
 
-pub(crate) fn get_client() -> HyperClient {
-    HyperClient::default()
-}
+/// A connection a [`crate::pool::Pool`] can keep alive
+pub(crate) type Connection = HyperClient;
 
 #[derive(Debug)]
 pub struct Req {
     req: Builder,
     body: Body,
-    pub(crate) client: Option<HyperClient>,
+    pool: Option<Pool>,
 }
 
 impl<M, U> TryFrom<(M, U)> for crate::Request
@@ -48,11 +69,14 @@ where
     fn try_from(value: (M, U)) -> Result<Self, Self::Error> {
         let req = Builder::new().method(value.0).uri(value.1);
 
-        Ok(crate::Request(Req {
-            req,
-            body: Body::empty(),
-            client: None,
-        }))
+        Ok(crate::Request(
+            Req {
+                req,
+                body: Body::empty(),
+                pool: None,
+            },
+            Default::default(),
+        ))
     }
 }
 impl Req {
@@ -62,7 +86,7 @@ impl Req {
         Req {
             req,
             body: Body::empty(),
-            client: None,
+            pool: None,
         }
     }
     fn _query(&mut self, query: String) -> Result<(), Error> {
@@ -108,19 +132,31 @@ impl crate::request::Requests for Req {
     fn new(meth: &str, uri: &str) -> Result<Req, Error> {
         Ok(Self::init(Method::from_str(meth)?, uri))
     }
-    async fn send_request(mut self) -> Result<crate::Response, Error> {
+    async fn send_request(self, options: &Options) -> Result<Resp, Error> {
         let req = self.req.body(self.body)?;
 
-        let resp = if let Some(mut client) = self.client.take() {
-            client.request(req).await?
-        } else {
-            get_client().request(req).await?
+        let pool = self.pool.unwrap_or_else(crate::pool::pool);
+        let origin = connector::conn_key(req.uri())?;
+        let key = PoolKey::new(origin.clone(), options);
+        #[cfg(feature = "http3")]
+        let resp = match http3::client(&origin, &key, options, &pool).await? {
+            Some((key, mut client)) => {
+                let resp = client.request(req, options).await?;
+                pool.put(key, HyperClient::H3(client));
+                resp
+            }
+            None => over_tcp(req, key, options, &pool).await?,
         };
+        #[cfg(not(feature = "http3"))]
+        let resp = over_tcp(req, key, options, &pool).await?;
+        #[cfg(feature = "http3")]
+        http3::learn(&pool, &origin, options, resp.headers());
+        let idle = options.timeouts.body_idle;
 
-        #[cfg(not(all(feature = "mock_tests", test)))]
-        return Ok(crate::Response(Resp { resp }));
-        #[cfg(all(feature = "mock_tests", test))]
-        return Ok(crate::Response(Resp::Real(not_mocked::Resp { resp })));
+        #[cfg(not(feature = "mock"))]
+        return Ok(Resp { resp, idle });
+        #[cfg(feature = "mock")]
+        return Ok(Resp::Real(not_mocked::Resp { resp, idle }));
     }
     fn json<T: Serialize + ?Sized>(&mut self, json: &T) -> Result<(), Error> {
         let bytes = serde_json::to_string(&json)?;
@@ -154,14 +190,100 @@ impl crate::request::Requests for Req {
         self.req = take(&mut self.req).header(name, value);
         Ok(())
     }
+    fn set_pool(&mut self, pool: Pool) {
+        self.pool = Some(pool);
+    }
+    fn method(&self) -> String {
+        self.req
+            .method_ref()
+            .map_or("GET", Method::as_str)
+            .to_string()
+    }
+    fn url(&self) -> String {
+        self.req
+            .uri_ref()
+            .map(|u| u.to_string())
+            .unwrap_or_default()
+    }
+    async fn try_clone(&mut self) -> Option<Self> {
+        let mut req = Builder::new()
+            .method(self.req.method_ref()?.clone())
+            .uri(self.req.uri_ref()?.clone())
+            .version(*self.req.version_ref()?);
+        *req.headers_mut()? = self.req.headers_ref()?.clone();
+        Some(Req {
+            req,
+            body: self.body.try_clone()?,
+            pool: self.pool.clone(),
+        })
+    }
+    fn set_url(&mut self, url: &str) -> Result<(), Error> {
+        let uri = Uri::try_from(url)?;
+        self.req = take(&mut self.req).uri(uri);
+        Ok(())
+    }
+    fn change_to_get(&mut self) {
+        if self.req.method_ref() != Some(&Method::HEAD) {
+            self.req = take(&mut self.req).method(Method::GET);
+        }
+        self.body = Body::empty();
+        if let Some(hm) = self.req.headers_mut() {
+            hm.remove(CONTENT_TYPE);
+            hm.remove(CONTENT_LENGTH);
+            hm.remove(CONTENT_ENCODING);
+            hm.remove(TRANSFER_ENCODING);
+        }
+    }
+    fn remove_header(&mut self, name: &str) {
+        if let Some(hm) = self.req.headers_mut() {
+            hm.remove(name);
+        }
+    }
+}
+/// HTTP/1.1 or HTTP/2
+async fn over_tcp(
+    req: Request<Body>,
+    key: PoolKey,
+    options: &Options,
+    pool: &Pool,
+) -> Result<Response<RespBody>, Error> {
+    let mut client = pool.take(&key).unwrap_or_default();
+    let resp = client.request(req, options, pool).await?;
+    pool.put(key, client);
+    Ok(resp.map(RespBody::Hyper))
 }
 use hyper::body::Buf;
 use serde::de::DeserializeOwned;
 
+/// Body of a response
+pub(crate) enum RespBody {
+    Hyper(Incoming),
+    #[cfg(feature = "http3")]
+    H3(Box<http3::Stream>),
+}
+impl RespBody {
+    fn poll_frame(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+        match self {
+            RespBody::Hyper(body) => Pin::new(body).poll_frame(cx).map_err(Error::Hyper),
+            #[cfg(feature = "http3")]
+            RespBody::H3(stream) => stream.poll_recv_data(cx).map(|r| {
+                r.map_err(Error::H3)
+                    .map(|o| o.map(|mut d| Frame::data(d.copy_to_bytes(d.remaining()))))
+                    .transpose()
+            }),
+        }
+    }
+}
+
 mod not_mocked {
     use super::*;
     pub struct Resp {
-        pub(super) resp: Response<Incoming>,
+        pub(super) resp: Response<RespBody>,
+        /// body_idle timeout
+        pub(super) idle: Option<Duration>,
     }
     impl crate::response::Responses for Resp {
         fn status(&self) -> u16 {
@@ -171,11 +293,11 @@ mod not_mocked {
             self.resp.status().canonical_reason().unwrap_or("")
         }
         async fn json<D: DeserializeOwned>(&mut self) -> Result<D, Error> {
-            let reader = aggregate(self.resp.body_mut()).await?.reader();
+            let reader = aggregate(self.resp.body_mut(), self.idle).await?.reader();
             Ok(serde_json::from_reader(reader)?)
         }
         async fn bytes(&mut self) -> Result<Vec<u8>, Error> {
-            let mut b = aggregate(self.resp.body_mut()).await?;
+            let mut b = aggregate(self.resp.body_mut(), self.idle).await?;
             let capacity = b.remaining();
             //Ok(b.copy_to_bytes(capacity).into())
             let mut v = Vec::with_capacity(capacity);
@@ -191,6 +313,21 @@ mod not_mocked {
             let b = self.bytes().await?;
             Ok(String::from_utf8_lossy(&b).to_string())
         }
+        fn into_stream(self) -> futures::stream::BoxStream<'static, io::Result<Bytes>> {
+            let idle = self.idle;
+            let stream =
+                futures::stream::try_unfold(self.resp.into_body(), move |mut body| async move {
+                    while let Some(f) =
+                        timeout(idle, TimeoutKind::BodyIdle, Framed(&mut body)).await?
+                    {
+                        if let Ok(d) = f.map_err(io::Error::other)?.into_data() {
+                            return Ok(Some((d, body)));
+                        }
+                    }
+                    Ok(None)
+                });
+            Box::pin(stream)
+        }
         fn get_header(&self, name: HeaderName) -> Option<&HeaderValue> {
             self.resp.headers().get(name)
         }
@@ -203,21 +340,23 @@ mod not_mocked {
     }
 }
 
-#[cfg(not(all(feature = "mock_tests", test)))]
+#[cfg(not(feature = "mock"))]
 pub use not_mocked::Resp;
-#[cfg(all(feature = "mock_tests", test))]
+#[cfg(feature = "mock")]
 pub type Resp = crate::mock::Resp<not_mocked::Resp>;
 
-#[cfg(all(feature = "mock_tests", test))]
+#[cfg(feature = "mock")]
 impl crate::mock::MockedRequest for Req {
-    /// on error, return full body
-    fn assert_body_bytes(&mut self, should_be: &[u8]) -> Result<(), Vec<u8>> {
-        let is = &self.body.0;
-        if is != should_be {
-            Err(is.clone())
-        } else {
-            Ok(())
+    async fn body_bytes(&mut self) -> io::Result<Vec<u8>> {
+        if let Inner::Streamed { stream, .. } = &mut self.body.0 {
+            use futures::TryStreamExt;
+            let chunks: Vec<Bytes> = stream.try_collect().await?;
+            self.body = chunks.concat().into();
         }
+        let Inner::Full(is) = &self.body.0 else {
+            unreachable!()
+        };
+        Ok(is.clone())
     }
     fn get_headers(&self, name: &str) -> Option<Vec<crate::mock::MockHeaderValue>> {
         let name = HeaderName::from_str(name).unwrap();
@@ -275,22 +414,23 @@ impl Buf for FracturedBuf {
     }
 }
 /// Helper for aggregate function. Polls a single frame from an incoming body
-struct Framed<'a>(&'a mut Incoming);
+struct Framed<'a>(&'a mut RespBody);
 
 impl futures::Future for Framed<'_> {
-    type Output = Option<Result<hyper::body::Frame<Bytes>, hyper::Error>>;
+    type Output = Option<Result<hyper::body::Frame<Bytes>, Error>>;
 
     fn poll(
         mut self: std::pin::Pin<&mut Self>,
         ctx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Self::Output> {
-        std::pin::Pin::new(&mut self.0).poll_frame(ctx)
+        self.0.poll_frame(ctx)
     }
 }
-/// read an incoming body to (fragmented) memory
-async fn aggregate(body: &mut Incoming) -> Result<FracturedBuf, Error> {
+/// read an incoming body to (fragmented) memory.
+/// Fails if no frame arrives within `idle`
+async fn aggregate(body: &mut RespBody, idle: Option<Duration>) -> Result<FracturedBuf, Error> {
     let mut v = std::collections::VecDeque::new();
-    while let Some(f) = Framed(body).await {
+    while let Some(f) = timeout(idle, TimeoutKind::BodyIdle, Framed(body)).await? {
         if let Ok(d) = f?.into_data() {
             v.push_back(d);
         }
@@ -311,6 +451,10 @@ pub enum Error {
     InvalidUri(InvalidUri),
     Urlencoded(serde_urlencoded::ser::Error),
     Io(std::io::Error),
+    #[cfg(feature = "http3")]
+    H3(h3::error::StreamError),
+    #[cfg(feature = "http3")]
+    H3Connection(h3::error::ConnectionError),
 }
 impl std::error::Error for Error {}
 use std::fmt;
@@ -328,29 +472,39 @@ impl fmt::Display for Error {
             Error::InvalidUri(i) => write!(f, "{}", i),
             Error::Urlencoded(i) => write!(f, "{}", i),
             Error::Io(i) => write!(f, "{}", i),
+            #[cfg(feature = "http3")]
+            Error::H3(i) => write!(f, "{}", i),
+            #[cfg(feature = "http3")]
+            Error::H3Connection(i) => write!(f, "{}", i),
         }
     }
 }
 impl From<Error> for crate::Error {
     fn from(e: Error) -> Self {
         match e {
-            Error::Io(error) => Self::Io(error),
+            Error::Io(error) => error.into(),
             Error::Hyper(h) => {
-                //It might be an IO error. If so, return it as such
-                if let Some(io) = std::error::Error::source(&h)
-                    .and_then(|err| err.downcast_ref::<std::io::Error>())
-                {
-                    let io_e = if let Some(code) = io.raw_os_error() {
-                        std::io::Error::from_raw_os_error(code)
-                    //}else if let Some(error) = io.into_inner() {
-                    //    std::io::Error::new(io.kind(), error)
-                    } else {
-                        io.kind().into()
-                    };
-                    Self::Io(io_e)
-                } else {
-                    Self::Other(Error::Hyper(h))
+                //It might be an IO error (maybe wrapped in another hyper error).
+                //If so, return it as such
+                let mut source = std::error::Error::source(&h);
+                while let Some(err) = source {
+                    if let Some(io) = err.downcast_ref::<std::io::Error>() {
+                        if let Some(typed) = crate::Error::typed(io) {
+                            return typed;
+                        }
+                        let io_e = if let Some(code) = io.raw_os_error() {
+                            std::io::Error::from_raw_os_error(code)
+                        } else if io.get_ref().is_some() {
+                            //keep the message
+                            std::io::Error::new(io.kind(), io.to_string())
+                        } else {
+                            io.kind().into()
+                        };
+                        return Self::Io(io_e);
+                    }
+                    source = err.source();
                 }
+                Self::Other(Error::Hyper(h))
             }
             e => Self::Other(e),
         }
@@ -406,6 +560,12 @@ impl From<HTTPError> for Error {
         Self::Http(e)
     }
 }
+#[cfg(feature = "http3")]
+impl From<h3::error::StreamError> for Error {
+    fn from(e: h3::error::StreamError) -> Self {
+        Self::H3(e)
+    }
+}
 //Req::query
 impl From<serde_qs::Error> for Error {
     fn from(e: serde_qs::Error) -> Self {
@@ -413,53 +573,99 @@ impl From<serde_qs::Error> for Error {
     }
 }
 
-#[derive(Debug)]
-pub struct Body(Vec<u8>);
+/// Body of a request. Either in memory or streamed
+pub struct Body(Inner);
+enum Inner {
+    Full(Vec<u8>),
+    Streamed {
+        stream: Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>,
+        len: Option<u64>,
+    },
+}
 impl Body {
     fn empty() -> Self {
-        Self(vec![])
+        Self(Inner::Full(vec![]))
+    }
+    pub(crate) fn from_reader<R>(reader: R, len: Option<u64>) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        Self::from_stream(ReaderStream::new(reader), len)
+    }
+    pub(crate) fn from_stream<S>(stream: S, len: Option<u64>) -> Self
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        Self(Inner::Streamed {
+            stream: Box::pin(stream),
+            len,
+        })
+    }
+    /// a copy of the body, if it is in memory
+    fn try_clone(&self) -> Option<Self> {
+        match &self.0 {
+            Inner::Full(v) => Some(Self(Inner::Full(v.clone()))),
+            Inner::Streamed { .. } => None,
+        }
+    }
+}
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Inner::Full(v) => f.debug_tuple("Body").field(v).finish(),
+            Inner::Streamed { len, .. } => f.debug_struct("Body").field("len", len).finish(),
+        }
     }
 }
 impl From<String> for Body {
     #[inline]
     fn from(t: String) -> Self {
-        Body(t.into_bytes())
+        Body(Inner::Full(t.into_bytes()))
     }
 }
 impl From<Vec<u8>> for Body {
     #[inline]
     fn from(t: Vec<u8>) -> Self {
-        Body(t)
+        Body(Inner::Full(t))
     }
 }
 impl From<&'static [u8]> for Body {
     #[inline]
     fn from(t: &'static [u8]) -> Self {
-        Body(t.to_vec())
+        Body(Inner::Full(t.to_vec()))
     }
 }
 impl From<&'static str> for Body {
     #[inline]
     fn from(t: &'static str) -> Self {
-        Body(t.as_bytes().to_vec())
+        Body(Inner::Full(t.as_bytes().to_vec()))
     }
 }
 impl hyper::body::Body for Body {
     type Data = Bytes;
-    type Error = Infallible;
+    type Error = io::Error;
 
     fn poll_frame(
-        mut self: std::pin::Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        if self.0.is_empty() {
-            std::task::Poll::Ready(None)
-        } else {
-            let v: Vec<u8> = std::mem::take(self.0.as_mut());
-            std::task::Poll::Ready(Some(Ok(Frame::data(v.into()))))
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match &mut self.0 {
+            Inner::Full(v) if v.is_empty() => Poll::Ready(None),
+            Inner::Full(v) => {
+                let v: Vec<u8> = std::mem::take(v);
+                Poll::Ready(Some(Ok(Frame::data(v.into()))))
+            }
+            Inner::Streamed { stream, .. } => stream
+                .as_mut()
+                .poll_next(cx)
+                .map(|o| o.map(|r| r.map(Frame::data))),
         }
     }
     fn size_hint(&self) -> SizeHint {
-        SizeHint::with_exact(self.0.len() as u64)
+        match &self.0 {
+            Inner::Full(v) => SizeHint::with_exact(v.len() as u64),
+            Inner::Streamed { len: Some(l), .. } => SizeHint::with_exact(*l),
+            Inner::Streamed { len: None, .. } => SizeHint::default(),
+        }
     }
 }
@@ -20,10 +20,10 @@
  */
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
-#[cfg(all(feature = "mock_tests", any(test, docsrs)))]
+#[cfg(feature = "mock")]
 mod mock;
-#[cfg(all(feature = "mock_tests", any(test, docsrs)))]
-#[cfg_attr(docsrs, doc(cfg(all(test, feature = "mock_tests"))))]
+#[cfg(feature = "mock")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mock")))]
 #[doc(inline)]
 pub use mock::{Mock, MockErr, MockedEndpoint};
 
@@ -40,6 +40,9 @@ mod tcp;
 #[cfg_attr(docsrs, doc(cfg(feature = "proxies")))]
 #[doc(inline)]
 pub use tcp::proxy;
+#[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+#[doc(inline)]
+pub use tcp::resolve;
 
 #[cfg(feature = "use_async_h1")]
 #[path = "a_h1/mod.rs"]
@@ -50,16 +53,40 @@ mod imp;
 mod imp;
 
 mod body;
+#[cfg(feature = "cookies")]
+mod cookies;
 mod header;
-//mod session;
+pub mod multipart;
+pub mod pool;
+pub mod redirect;
 mod request;
 mod response;
+mod session;
+mod timeout;
 
-pub use request::Request;
-pub use response::Response;
-//pub use session::Session;
 pub use body::Body;
+pub use bytes::Bytes;
+#[cfg(feature = "cookies")]
+pub use cookies::CookieJar;
 pub use header::{HeaderName, HeaderValue};
+#[cfg(all(feature = "use_hyper", feature = "http2"))]
+pub use imp::Http2Config;
+#[cfg(feature = "http3")]
+pub use imp::Http3;
+pub use multipart::Multipart;
+pub use request::Request;
+pub use response::{BodyReader, Response};
+pub use session::Session;
+#[cfg(all(
+    any(feature = "use_hyper", feature = "use_async_h1"),
+    any(
+        feature = "rustls_byoc",
+        feature = "hyper_native_tls",
+        feature = "async_native_tls"
+    )
+))]
+pub use tcp::{TlsConfig, TlsError};
+pub use timeout::{TimeoutKind, Timeouts};
 
 #[derive(Debug)]
 pub enum Error {
@@ -67,9 +94,29 @@ pub enum Error {
     HTTPServerErr(u16, Response),
     HTTPClientErr(u16, Response),
     Other(imp::Error),
-    #[cfg(all(feature = "mock_tests", any(test, docsrs)))]
-    #[cfg_attr(docsrs, doc(cfg(all(test, feature = "mock_tests"))))]
+    /// One of the [`Timeouts`] expired
+    Timeout(TimeoutKind),
+    /// The proxy did not open a connection
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        feature = "proxies"
+    ))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "proxies")))]
+    Proxy(proxy::ProxyError),
+    /// The request did not match the [`Mock`]
+    #[cfg(feature = "mock")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "mock")))]
     Mock(mock::MockErr),
+    /// The TLS connection failed
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        any(
+            feature = "rustls_byoc",
+            feature = "hyper_native_tls",
+            feature = "async_native_tls"
+        )
+    ))]
+    Tls(TlsError),
 }
 
 impl std::error::Error for Error {}
@@ -81,17 +128,61 @@ impl fmt::Display for Error {
             Error::HTTPClientErr(i, r) => write!(f, "{} {}", i, r.status()),
             Error::HTTPServerErr(i, r) => write!(f, "{} {}", i, r.status()),
             Error::Io(i) => write!(f, "{}", i),
-            #[cfg(all(feature = "mock_tests", test))]
+            Error::Timeout(t) => write!(f, "{}", t),
+            #[cfg(all(
+                any(feature = "use_hyper", feature = "use_async_h1"),
+                feature = "proxies"
+            ))]
+            Error::Proxy(p) => write!(f, "{}", p),
+            #[cfg(feature = "mock")]
             Error::Mock(m) => write!(f, "{}", m),
+            #[cfg(all(
+                any(feature = "use_hyper", feature = "use_async_h1"),
+                any(
+                    feature = "rustls_byoc",
+                    feature = "hyper_native_tls",
+                    feature = "async_native_tls"
+                )
+            ))]
+            Error::Tls(t) => write!(f, "{}", t),
         }
     }
 }
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::typed(&e).unwrap_or(Self::Io(e))
+    }
+}
+impl Error {
+    /// The error of this crate that `e` carries, if any
+    pub(crate) fn typed(e: &std::io::Error) -> Option<Self> {
+        #[cfg(all(
+            any(feature = "use_hyper", feature = "use_async_h1"),
+            feature = "proxies"
+        ))]
+        if let Some(p) = proxy::ProxyError::of(e) {
+            return Some(Self::Proxy(p));
+        }
+        #[cfg(all(
+            any(feature = "use_hyper", feature = "use_async_h1"),
+            any(
+                feature = "rustls_byoc",
+                feature = "hyper_native_tls",
+                feature = "async_native_tls"
+            )
+        ))]
+        if let Some(t) = TlsError::of(e) {
+            return Some(Self::Tls(t));
+        }
+        timeout::kind_of(e).map(Self::Timeout)
+    }
+}
 impl From<std::convert::Infallible> for Error {
     fn from(_e: std::convert::Infallible) -> Self {
         unreachable!();
     }
 }
-#[cfg(all(feature = "mock_tests", test))]
+#[cfg(feature = "mock")]
 impl From<mock::MockErr> for Error {
     fn from(e: mock::MockErr) -> Self {
         Self::Mock(e)
@@ -149,6 +240,18 @@ mod tests {
         assert_eq!(req, should_be);
         Ok(())
     }
+    /// read a request head (until the empty line)
+    pub(crate) async fn read_head<S: ReadExt + Unpin>(stream: &mut S) -> std::io::Result<String> {
+        let mut head = Vec::new();
+        let mut b = [0u8];
+        while !head.ends_with(b"\r\n\r\n") {
+            if stream.read(&mut b).await? == 0 {
+                break;
+            }
+            head.push(b[0]);
+        }
+        Ok(String::from_utf8_lossy(&head).to_string())
+    }
     pub(crate) async fn listen_somewhere() -> Result<(TcpListener, u16, String), std::io::Error> {
         let listener = TcpListener::bind("127.0.0.1:0").await?;
         let addr = listener.local_addr()?;
@@ -160,7 +263,6 @@ mod tests {
     fn get() {
         async fn server(listener: TcpListener, host: String, port: u16) -> std::io::Result<bool> {
             let (mut stream, _) = listener.accept().await?;
-            let mut output = Vec::with_capacity(1);
             assert_stream(
                 &mut stream,
                 format!(
@@ -174,7 +276,7 @@ mod tests {
             stream
                 .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabc")
                 .await?;
-            let _ = stream.read(&mut output).await?;
+            //the connection is kept in the global pool, it is not closed
             Ok(true)
         }
         block_on(async {
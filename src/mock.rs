@@ -1,4 +1,4 @@
-use std::{cell::RefCell, collections::HashMap, fmt::Debug, iter};
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, future::Future, io, iter};
 
 use crate::{
     imp::{Error as ErrorImp, HeaderName as HNameImp, HeaderValue as HValImp},
@@ -10,7 +10,13 @@ thread_local! {
 }
 /// Mock Responses and validate Requests.
 /// All responses of a thread will be checked against the Mock if **at least one Endpoint is Mocked**.
-/// Otherwise a normal web request is done
+/// Otherwise a normal web request is done.
+///
+/// Needs the `mock` feature. Crates that use it for their tests enable it in `[dev-dependencies]`:
+/// ```toml
+/// [dev-dependencies]
+/// generic-async-http-client = { version = "0.7", features = ["mock"] }
+/// ```
 ///
 /// ```
 /// # use futures::executor::block_on;
@@ -132,15 +138,23 @@ impl Mock {
             f(e);
         });
     }
-    pub(crate) fn check<R>(mut req: impl MockedRequest) -> Result<Resp<R>, MockErr> {
-        VALIDATOR.with_borrow(|v| match v.v.get(&req.endpoint()) {
+    pub(crate) async fn check<R>(mut req: impl MockedRequest) -> Result<Resp<R>, MockErr> {
+        let endpoint = req.endpoint();
+        //the body might be streamed, so read it before borrowing the endpoint
+        let Some(req_body) =
+            VALIDATOR.with_borrow(|v| v.v.get(&endpoint).map(|v| v.req_body.clone()))
+        else {
+            return Err(MockErr::NoResponseProvided);
+        };
+        if let Some(b) = req_body {
+            let is = req.body_bytes().await.map_err(MockErr::BodyUnreadable)?;
+            if is != b.0 {
+                return Err(MockErr::BodyAssertionFailed(is));
+            }
+        }
+        VALIDATOR.with_borrow(|v| match v.v.get(&endpoint) {
             None => Err(MockErr::NoResponseProvided),
             Some(v) => {
-                if let Some(b) = v.req_body.as_ref() {
-                    if let Err(e) = req.assert_body_bytes(&b.0) {
-                        return Err(MockErr::BodyAssertionFailed(e));
-                    }
-                }
                 if let Some(check_header) = &v.req_header {
                     for (h, v) in check_header {
                         match req.get_headers(&h.0) {
@@ -177,6 +191,8 @@ pub enum MockErr {
     NoResponseProvided,
     BodyAssertionFailed(Vec<u8>),
     HeaderAssertionFailed(String),
+    /// The request body could not be read for the assertion
+    BodyUnreadable(io::Error),
 }
 impl std::fmt::Display for MockErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -186,8 +202,8 @@ impl std::fmt::Display for MockErr {
 impl std::error::Error for MockErr {}
 
 pub trait MockedRequest {
-    /// on error, return full body
-    fn assert_body_bytes(&mut self, should_be: &[u8]) -> Result<(), Vec<u8>>;
+    /// read the full body
+    fn body_bytes(&mut self) -> impl Future<Output = io::Result<Vec<u8>>>;
     fn get_headers(&self, name: &str) -> Option<Vec<MockHeaderValue>>;
     fn endpoint(&self) -> Endpoint;
 }
@@ -279,6 +295,11 @@ impl From<HValImp> for MockHeaderValue {
 
 #[allow(private_interfaces)]
 pub enum Resp<R> {
+    //the dummy backend never has a real response
+    #[cfg_attr(
+        not(any(feature = "use_hyper", feature = "use_async_h1")),
+        allow(dead_code)
+    )]
     Real(R),
     Fake(crate::mock::MockResp),
 }
@@ -321,6 +342,15 @@ where
             Resp::Fake(resp) => Ok(String::from_utf8_lossy(&resp.body.0).to_string()),
         }
     }
+    fn into_stream(self) -> futures::stream::BoxStream<'static, std::io::Result<bytes::Bytes>> {
+        match self {
+            Resp::Real(resp) => resp.into_stream(),
+            Resp::Fake(resp) => {
+                let chunk = Ok(bytes::Bytes::from(resp.body.0));
+                Box::pin(futures::stream::iter(Some(chunk)))
+            }
+        }
+    }
     fn get_header(&self, name: HNameImp) -> Option<&HValImp> {
         match self {
             Resp::Real(resp) => resp.get_header(name),
@@ -392,6 +422,79 @@ mod tests {
         .unwrap();
     }
     #[test]
+    fn mock_stream() {
+        block_on(async {
+            Mock::update("GET", "http://example.com/stream", |r| {
+                r.set_response(200, "mocked stream");
+            });
+
+            let resp = crate::Request::get("http://example.com/stream")
+                .exec()
+                .await?;
+            let mut body = Vec::new();
+            futures::AsyncReadExt::read_to_end(&mut resp.into_reader(), &mut body).await?;
+            assert_eq!(body, b"mocked stream");
+            Result::<(), Error>::Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn query_and_json() {
+        block_on(async {
+            Mock::update("POST", "http://example.com/p?a=1", |r| {
+                r.assert_body(r#"{"b":2}"#);
+                r.add_header_assertion("content-type", "application/json")
+                    .unwrap();
+                r.set_response(200, "ok");
+            });
+            let mut resp = crate::Request::post("http://example.com/p")
+                .query(&std::collections::BTreeMap::from([("a", 1)]))?
+                .json(&std::collections::BTreeMap::from([("b", 2)]))?
+                .exec()
+                .await?;
+            assert_eq!(resp.text().await?, "ok");
+            Result::<(), Error>::Ok(())
+        })
+        .unwrap();
+    }
+    /// a streamed body is read without blocking the runtime
+    #[test]
+    fn assert_streamed_body() {
+        block_on(async {
+            Mock::update("PUT", "http://example.com/up", |r| {
+                r.assert_body("abc");
+                r.set_response(200, "ok");
+            });
+            let (mut tx, rx) = futures::channel::mpsc::channel(1);
+            let feed = async move {
+                futures::SinkExt::send(&mut tx, Ok(Bytes::from_static(b"abc"))).await
+            };
+            let exec = async {
+                crate::Request::put("http://example.com/up")
+                    .body(Body::from_stream(rx))?
+                    .exec()
+                    .await
+            };
+            let (sent, resp) = futures::future::join(feed, exec).await;
+            sent.expect("not received");
+            assert_eq!(resp?.text().await?, "ok");
+
+            let broken = futures::stream::iter([Err(std::io::Error::other("gone"))]);
+            let err = crate::Request::put("http://example.com/up")
+                .body(Body::from_stream(broken))?
+                .exec()
+                .await
+                .expect_err("should fail");
+            assert!(
+                matches!(err, Error::Mock(MockErr::BodyUnreadable(_))),
+                "{:?}",
+                err
+            );
+            Result::<(), Error>::Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
     fn error_on_miss() {
         block_on(async {
             Mock::update("GET", "anything", |_r| {});
@@ -0,0 +1,318 @@
+//! `multipart/form-data` bodies. See [`Multipart`]
+use crate::{body::ReaderStream, Body, Error, HeaderName, HeaderValue};
+use bytes::Bytes;
+use futures::{AsyncRead, Stream, StreamExt};
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryInto,
+    hash::{BuildHasher, Hasher},
+    io,
+    pin::Pin,
+};
+
+type Chunks = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// A `multipart/form-data` form. Attach it with [`Request::multipart`](crate::Request::multipart).
+///
+/// ```
+/// # use generic_async_http_client::{Request, Error, multipart::{Multipart, Part}};
+/// # async fn upload(file: async_std::fs::File) -> Result<(), Error> {
+///     let len = file.metadata().await?.len();
+///     let form = Multipart::new()
+///         .text("title", "holiday")
+///         .part(
+///             "picture",
+///             Part::reader(file, Some(len))
+///                 .file_name("beach.jpg")
+///                 .content_type("image/jpeg"),
+///         );
+///     Request::post("http://example.com/").multipart(form)?.exec().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Multipart {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+impl Multipart {
+    /// An empty form with a random boundary
+    pub fn new() -> Multipart {
+        let mut boundary = String::with_capacity(64);
+        for _ in 0..4 {
+            let r = RandomState::new().build_hasher().finish();
+            boundary.push_str(&format!("{:016x}", r));
+        }
+        Multipart {
+            boundary,
+            parts: Vec::new(),
+        }
+    }
+    /// The boundary that separates the parts
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+    /// Add a text field
+    pub fn text(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.part(name, Part::text(value))
+    }
+    /// Add a field with binary content
+    pub fn bytes(self, name: impl Into<String>, value: impl Into<Bytes>) -> Self {
+        self.part(name, Part::bytes(value))
+    }
+    /// Add a custom part
+    pub fn part(mut self, name: impl Into<String>, part: Part) -> Self {
+        self.parts.push((name.into(), part));
+        self
+    }
+    /// `Content-Type` header of the form
+    pub(crate) fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+    /// Encode the form. If all parts are in memory, the body is as well
+    pub(crate) fn into_body(self) -> Body {
+        let mut len = Some(0u64);
+        let mut buffered: Vec<u8> = Vec::new();
+        let mut chunks: Vec<Chunks> = Vec::new();
+
+        for (name, part) in self.parts {
+            buffered.extend_from_slice(b"--");
+            buffered.extend_from_slice(self.boundary.as_bytes());
+            buffered.extend_from_slice(b"\r\n");
+            part.write_head(&name, &mut buffered);
+            match part.data {
+                Data::Bytes(b) => buffered.extend_from_slice(&b),
+                Data::Stream(stream, l) => {
+                    len = len.zip(l).map(|(a, b)| a + buffered.len() as u64 + b);
+                    let head = Bytes::from(std::mem::take(&mut buffered));
+                    chunks.push(Box::pin(futures::stream::iter(Some(Ok(head)))));
+                    chunks.push(stream);
+                }
+            }
+            buffered.extend_from_slice(b"\r\n");
+        }
+        buffered.extend_from_slice(b"--");
+        buffered.extend_from_slice(self.boundary.as_bytes());
+        buffered.extend_from_slice(b"--\r\n");
+
+        if chunks.is_empty() {
+            return buffered.into();
+        }
+        let len = len.map(|l| l + buffered.len() as u64);
+        chunks.push(Box::pin(futures::stream::iter(Some(Ok(Bytes::from(
+            buffered,
+        ))))));
+        Body::from_sized_stream(futures::stream::iter(chunks).flatten(), len)
+    }
+}
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl std::fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Multipart")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts)
+            .finish()
+    }
+}
+/// A single field of a [`Multipart`] form
+pub struct Part {
+    data: Data,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+enum Data {
+    Bytes(Bytes),
+    Stream(Chunks, Option<u64>),
+}
+impl Part {
+    fn new(data: Data) -> Part {
+        Part {
+            data,
+            file_name: None,
+            content_type: None,
+            headers: Vec::new(),
+        }
+    }
+    /// A text field
+    pub fn text(value: impl Into<String>) -> Part {
+        Self::new(Data::Bytes(value.into().into()))
+    }
+    /// A field with binary content
+    pub fn bytes(value: impl Into<Bytes>) -> Part {
+        Self::new(Data::Bytes(value.into()))
+    }
+    /// Stream the content from `reader`. If `len` is known,
+    /// the `Content-Length` of the request can be calculated.
+    pub fn reader<R>(reader: R, len: Option<u64>) -> Part
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        Self::new(Data::Stream(Box::pin(ReaderStream::new(reader)), len))
+    }
+    /// Like [`Part::reader`], but for a `tokio` reader like `tokio::fs::File`
+    #[cfg(feature = "use_hyper")]
+    pub fn tokio_reader<R>(reader: R, len: Option<u64>) -> Part
+    where
+        R: tokio::io::AsyncRead + Send + Unpin + 'static,
+    {
+        Self::reader(crate::body::TokioReader(reader), len)
+    }
+    /// Stream the content from chunks of `stream`
+    pub fn stream<S>(stream: S) -> Part
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        Self::new(Data::Stream(Box::pin(stream), None))
+    }
+    /// Set the filename of the part
+    pub fn file_name(mut self, name: impl Into<String>) -> Part {
+        self.file_name = Some(name.into());
+        self
+    }
+    /// Set the `Content-Type` of the part
+    pub fn content_type(mut self, mime: impl Into<String>) -> Part {
+        self.content_type = Some(mime.into());
+        self
+    }
+    /// Add an additional header to the part
+    pub fn header<N, V, E1, E2>(mut self, name: N, value: V) -> Result<Part, Error>
+    where
+        N: TryInto<HeaderName, Error = E1>,
+        V: TryInto<HeaderValue, Error = E2>,
+        Error: From<E1>,
+        Error: From<E2>,
+    {
+        let val: HeaderValue = value.try_into()?;
+        let name: HeaderName = name.try_into()?;
+        self.headers.push((name, val));
+        Ok(self)
+    }
+    /// write the headers of this part
+    fn write_head(&self, name: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(b"Content-Disposition: form-data; name=\"");
+        escape(name, out);
+        out.push(b'"');
+        if let Some(file_name) = &self.file_name {
+            out.extend_from_slice(b"; filename=\"");
+            escape(file_name, out);
+            out.push(b'"');
+        }
+        out.extend_from_slice(b"\r\n");
+        if let Some(mime) = &self.content_type {
+            out.extend_from_slice(b"Content-Type: ");
+            out.extend_from_slice(mime.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+        for (n, v) in &self.headers {
+            let n: &[u8] = n.as_ref();
+            out.extend_from_slice(n);
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(v.as_ref());
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(b"\r\n");
+    }
+}
+impl std::fmt::Debug for Part {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = match &self.data {
+            Data::Bytes(b) => Some(b.len() as u64),
+            Data::Stream(_, l) => *l,
+        };
+        f.debug_struct("Part")
+            .field("len", &len)
+            .field("file_name", &self.file_name)
+            .field("content_type", &self.content_type)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+/// percent encode `"`, CR and LF, like browsers do
+fn escape(s: &str, out: &mut Vec<u8>) {
+    for b in s.bytes() {
+        match b {
+            b'"' => out.extend_from_slice(b"%22"),
+            b'\r' => out.extend_from_slice(b"%0D"),
+            b'\n' => out.extend_from_slice(b"%0A"),
+            b => out.push(b),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "use_hyper", feature = "use_async_h1")))]
+mod tests {
+    use super::*;
+    use crate::tests::{
+        block_on, listen_somewhere, read_head, spawn, ReadExt, TcpListener, WriteExt,
+    };
+    use crate::Request;
+
+    async fn server(listener: TcpListener, boundary: String) -> io::Result<bool> {
+        let body = format!(
+            "--{b}\r\n\
+            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+            some text\r\n\
+            --{b}\r\n\
+            Content-Disposition: form-data; name=\"bin\"; filename=\"a%22b.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\
+            x-extra: 1\r\n\r\n\
+            \x00\x01\r\n\
+            --{b}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"f.txt\"\r\n\r\n\
+            streamed\r\n\
+            --{b}--\r\n",
+            b = boundary
+        );
+        let (mut stream, _) = listener.accept().await?;
+        let head = read_head(&mut stream).await?.to_ascii_lowercase();
+        assert!(
+            head.contains(&format!(
+                "\r\ncontent-type: multipart/form-data; boundary={}\r\n",
+                boundary
+            )),
+            "{}",
+            head
+        );
+        assert!(
+            head.contains(&format!("\r\ncontent-length: {}\r\n", body.len())),
+            "{}",
+            head
+        );
+        let mut b = vec![0; body.len()];
+        stream.read_exact(&mut b).await?;
+        assert_eq!(String::from_utf8_lossy(&b), body);
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await?;
+        Ok(true)
+    }
+    #[test]
+    fn upload() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+
+            let file = futures::io::Cursor::new(b"streamed");
+            let form = Multipart::new()
+                .text("text", "some text")
+                .part(
+                    "bin",
+                    Part::bytes(&b"\x00\x01"[..])
+                        .file_name("a\"b.bin")
+                        .content_type("application/octet-stream")
+                        .header("x-extra", "1")?,
+                )
+                .part("file", Part::reader(file, Some(8)).file_name("f.txt"));
+            let t = spawn(server(listener, form.boundary().to_string()));
+
+            let r = Request::post(&format!("http://{}:{}/", host, port)).multipart(form)?;
+            assert_eq!(r.exec().await?.status_code(), 200);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
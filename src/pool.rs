@@ -0,0 +1,510 @@
+//! Connections that are kept alive for reuse.
+//!
+//! Requests share the global [`Pool`], unless they are done with a [`Session`](crate::Session)
+//! (which has its own pool) or [`Request::pool`](crate::Request::pool) is set.
+//! ```
+//! # use generic_async_http_client::{Request, Error, pool::{Pool, PoolConfig}};
+//! # use std::time::Duration;
+//! # async fn get() -> Result<(), Error> {
+//!     let pool = Pool::new(
+//!         PoolConfig::new()
+//!             .max_idle_per_host(4)
+//!             .max_connections(32)
+//!             .idle_timeout(Some(Duration::from_secs(30))),
+//!     );
+//!     Request::get("http://example.com/").pool(&pool).exec().await?;
+//!     println!("{:?}", pool.stats());
+//! # Ok(())
+//! # }
+//! ```
+#![cfg_attr(
+    not(any(feature = "use_hyper", feature = "use_async_h1")),
+    allow(dead_code)
+)]
+use crate::{imp, request::Options};
+use std::collections::HashMap;
+use std::future::poll_fn;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// scheme is https, host, port
+pub(crate) type ConnKey = (bool, String, u16);
+
+/// Limits of a [`Pool`].
+///
+/// By default, there is no limit on the number of connections
+/// and idle connections are closed after 90 seconds
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    max_idle_per_host: usize,
+    max_connections: Option<usize>,
+    idle_timeout: Option<Duration>,
+}
+impl PoolConfig {
+    pub fn new() -> PoolConfig {
+        PoolConfig {
+            max_idle_per_host: usize::MAX,
+            max_connections: None,
+            idle_timeout: Some(Duration::from_secs(90)),
+        }
+    }
+    /// Keep at most `max` idle connections to the same host. More are closed
+    pub fn max_idle_per_host(mut self, max: usize) -> Self {
+        self.max_idle_per_host = max;
+        self
+    }
+    /// Open at most `max` connections at once. Further requests wait until a connection is closed.
+    /// Idle connections are closed to make room
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+    /// Close connections that were not used for `d`. `None` keeps them forever
+    pub fn idle_timeout(mut self, d: Option<Duration>) -> Self {
+        self.idle_timeout = d;
+        self
+    }
+}
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counters of a [`Pool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PoolStats {
+    /// requests that reused a connection
+    pub hits: u64,
+    /// requests that needed a new connection
+    pub misses: u64,
+    /// connections waiting to be reused
+    pub idle: usize,
+    /// connections that are open
+    pub open: usize,
+}
+
+/// Connections that are kept alive for reuse. Clones share the connections.
+///
+/// Connections are only reused for requests to the same scheme, host and port,
+/// with the same proxy, [`TlsConfig`](crate::TlsConfig) and resolver.
+/// HTTP/2 and HTTP/3 connections serve many requests at once
+#[derive(Clone)]
+pub struct Pool(Arc<Inner>);
+struct Inner {
+    config: PoolConfig,
+    idle: Mutex<HashMap<PoolKey, Vec<Idle>>>,
+    /// shared with the permits, as they live as long as their connection
+    open: Arc<Mutex<Open>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// UDP port and expiry of HTTP/3, as announced by `Alt-Svc`
+    #[cfg(feature = "http3")]
+    alt_svc: Mutex<HashMap<ConnKey, (u16, Instant)>>,
+}
+struct Idle {
+    conn: imp::Connection,
+    since: Instant,
+}
+#[derive(Default)]
+struct Open {
+    count: usize,
+    waiting: Vec<Waker>,
+}
+
+impl Pool {
+    pub fn new(config: PoolConfig) -> Pool {
+        Pool(Arc::new(Inner {
+            config,
+            idle: Mutex::default(),
+            open: Arc::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            #[cfg(feature = "http3")]
+            alt_svc: Mutex::default(),
+        }))
+    }
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.0.hits.load(Ordering::Relaxed),
+            misses: self.0.misses.load(Ordering::Relaxed),
+            idle: self.lock_idle().values().map(Vec::len).sum(),
+            open: self
+                .0
+                .open
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .count,
+        }
+    }
+    /// Close all idle connections
+    pub fn clear(&self) {
+        let idle = std::mem::take(&mut *self.lock_idle());
+        drop(idle);
+    }
+    fn lock_idle(&self) -> std::sync::MutexGuard<'_, HashMap<PoolKey, Vec<Idle>>> {
+        self.0.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// A connection to `key` that can be used right away, if there is one
+    pub(crate) fn take(&self, key: &PoolKey) -> Option<imp::Connection> {
+        let timeout = self.0.config.idle_timeout;
+        let mut idle = self.lock_idle();
+        let mut stale = Vec::new();
+        let found = idle.get_mut(key).and_then(|list| {
+            //drop them outside of the lock
+            let (keep, gone) = std::mem::take(list).into_iter().partition(|i| {
+                !i.conn.is_closed() && timeout.is_none_or(|t| i.since.elapsed() < t)
+            });
+            *list = keep;
+            stale = gone;
+            //the most recently used one
+            let pos = list.iter().rposition(|i| i.conn.is_ready())?;
+            match list[pos].conn.share() {
+                Some(shared) => {
+                    list[pos].since = Instant::now();
+                    Some(shared)
+                }
+                None => Some(list.remove(pos).conn),
+            }
+        });
+        if idle.get(key).is_some_and(Vec::is_empty) {
+            idle.remove(key);
+        }
+        drop(idle);
+        drop(stale);
+        let counter = if found.is_some() {
+            &self.0.hits
+        } else {
+            &self.0.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+    /// Give a connection back for reuse
+    pub(crate) fn put(&self, key: PoolKey, conn: imp::Connection) {
+        if conn.is_closed() {
+            return;
+        }
+        let mut idle = self.lock_idle();
+        let list = idle.entry(key).or_default();
+        if conn.share().is_some() && list.iter().any(|i| i.conn.share().is_some()) {
+            //already there to be shared
+            return;
+        }
+        if list.len() >= self.0.config.max_idle_per_host {
+            return;
+        }
+        list.push(Idle {
+            conn,
+            since: Instant::now(),
+        });
+    }
+    /// The UDP port `origin` offers HTTP/3 on, if it did not expire
+    #[cfg(feature = "http3")]
+    pub(crate) fn alt_svc(&self, origin: &ConnKey) -> Option<u16> {
+        let mut alt_svc = self
+            .0
+            .alt_svc
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match alt_svc.get(origin) {
+            Some((port, until)) if *until > Instant::now() => Some(*port),
+            Some(_) => {
+                alt_svc.remove(origin);
+                None
+            }
+            None => None,
+        }
+    }
+    /// Remember that `origin` offers HTTP/3 on a port for some time, or forget it
+    #[cfg(feature = "http3")]
+    pub(crate) fn set_alt_svc(&self, origin: &ConnKey, alt: Option<(u16, Duration)>) {
+        let mut alt_svc = self
+            .0
+            .alt_svc
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match alt.and_then(|(port, max_age)| Some((port, Instant::now().checked_add(max_age)?))) {
+            Some(alt) => alt_svc.insert(origin.clone(), alt),
+            None => alt_svc.remove(origin),
+        };
+    }
+    /// Wait until another connection may be opened
+    pub(crate) async fn permit(&self) -> Permit {
+        poll_fn(|cx| {
+            let mut open = self.0.open.lock().unwrap_or_else(PoisonError::into_inner);
+            if self.0.config.max_connections.is_none_or(|m| open.count < m) {
+                open.count += 1;
+                return Poll::Ready(Permit(self.0.open.clone()));
+            }
+            if !open.waiting.iter().any(|w| w.will_wake(cx.waker())) {
+                open.waiting.push(cx.waker().clone());
+            }
+            drop(open);
+            self.evict_oldest();
+            Poll::Pending
+        })
+        .await
+    }
+    /// Close the idle connection that was not used for the longest time
+    fn evict_oldest(&self) {
+        //closed after the lock is released
+        let _evicted = {
+            let mut idle = self.lock_idle();
+            let oldest = idle
+                .iter()
+                .flat_map(|(k, list)| list.iter().enumerate().map(move |(i, e)| (k, i, e.since)))
+                .min_by_key(|(_, _, since)| *since)
+                .map(|(k, i, _)| (k.clone(), i));
+            oldest.and_then(|(k, i)| Some(idle.get_mut(&k)?.remove(i)))
+        };
+    }
+}
+impl Default for Pool {
+    fn default() -> Self {
+        Self::new(PoolConfig::default())
+    }
+}
+impl std::fmt::Debug for Pool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pool")
+            .field("config", &self.0.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Sets the global pool. It is used by all requests that do not have their own
+pub fn set_pool(pool: Pool) {
+    *GLOBAL_POOL.write().unwrap_or_else(PoisonError::into_inner) = Some(pool);
+}
+/// Returns the global pool
+pub fn pool() -> Pool {
+    if let Some(pool) = &*GLOBAL_POOL.read().unwrap_or_else(PoisonError::into_inner) {
+        return pool.clone();
+    }
+    GLOBAL_POOL
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(Pool::default)
+        .clone()
+}
+static GLOBAL_POOL: RwLock<Option<Pool>> = RwLock::new(None);
+
+/// Allows one connection to be open. It is given back on drop
+pub(crate) struct Permit(Arc<Mutex<Open>>);
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut open = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        open.count -= 1;
+        let waiting = std::mem::take(&mut open.waiting);
+        drop(open);
+        waiting.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// What a connection of a backend has to tell the pool
+pub(crate) trait Poolable: Sized {
+    /// The connection can not be used anymore
+    fn is_closed(&self) -> bool;
+    /// The connection can take a request now
+    fn is_ready(&self) -> bool;
+    /// Another handle to the connection, if it can serve concurrent requests (HTTP/2)
+    fn share(&self) -> Option<Self>;
+}
+
+/// Connections are only reused for requests with the same key
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PoolKey {
+    conn: ConnKey,
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        any(
+            feature = "rustls_byoc",
+            feature = "hyper_native_tls",
+            feature = "async_native_tls"
+        )
+    ))]
+    tls: Option<ByPtr<dyn std::any::Any + Send + Sync>>,
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        feature = "proxies"
+    ))]
+    proxy: Option<ByPtr<dyn crate::proxy::Proxy>>,
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    resolver: Option<ByPtr<dyn crate::resolve::Resolve>>,
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    ip_family: crate::resolve::IpFamily,
+    #[cfg(all(unix, any(feature = "use_hyper", feature = "use_async_h1")))]
+    unix_socket: Option<crate::tcp::UnixAddr>,
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    http2_prior_knowledge: bool,
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    http2: crate::Http2Config,
+    /// UDP port of an HTTP/3 connection
+    #[cfg(feature = "http3")]
+    quic: Option<u16>,
+}
+impl PoolKey {
+    #[cfg_attr(
+        not(any(feature = "use_hyper", feature = "use_async_h1")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn new(conn: ConnKey, options: &Options) -> PoolKey {
+        PoolKey {
+            conn,
+            #[cfg(all(
+                any(feature = "use_hyper", feature = "use_async_h1"),
+                any(
+                    feature = "rustls_byoc",
+                    feature = "hyper_native_tls",
+                    feature = "async_native_tls"
+                )
+            ))]
+            tls: options.tls.as_ref().map(|t| ByPtr(t.identity())),
+            #[cfg(all(
+                any(feature = "use_hyper", feature = "use_async_h1"),
+                feature = "proxies"
+            ))]
+            proxy: options.proxy.as_ref().map(|p| ByPtr(p.0.clone())),
+            #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+            resolver: options.resolver.as_ref().map(|r| ByPtr(r.0.clone())),
+            #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+            ip_family: options.ip_family,
+            #[cfg(all(unix, any(feature = "use_hyper", feature = "use_async_h1")))]
+            unix_socket: options.unix_socket.clone(),
+            #[cfg(all(feature = "use_hyper", feature = "http2"))]
+            http2_prior_knowledge: options.http2_prior_knowledge,
+            #[cfg(all(feature = "use_hyper", feature = "http2"))]
+            http2: options.http2.clone(),
+            #[cfg(feature = "http3")]
+            quic: None,
+        }
+    }
+    /// The key of an HTTP/3 connection to `port` instead
+    #[cfg(feature = "http3")]
+    pub(crate) fn quic(mut self, port: u16) -> PoolKey {
+        self.quic = Some(port);
+        self
+    }
+}
+/// Compares the `Arc`, not the value. Keeping it alive makes sure the address is not reused
+struct ByPtr<T: ?Sized>(Arc<T>);
+impl<T: ?Sized> Clone for ByPtr<T> {
+    fn clone(&self) -> Self {
+        ByPtr(self.0.clone())
+    }
+}
+impl<T: ?Sized> PartialEq for ByPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl<T: ?Sized> Eq for ByPtr<T> {}
+impl<T: ?Sized> Hash for ByPtr<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Arc::as_ptr(&self.0) as *const () as usize).hash(state)
+    }
+}
+
+#[cfg(all(test, any(feature = "use_hyper", feature = "use_async_h1")))]
+mod tests {
+    use super::*;
+    use crate::tests::{block_on, listen_somewhere, read_head, spawn, TcpListener, WriteExt};
+    use crate::Request;
+    use std::future::Future;
+
+    /// answers `n` requests on one connection, then closes it
+    async fn server(listener: TcpListener, n: usize) -> std::io::Result<bool> {
+        let (mut stream, _) = listener.accept().await?;
+        for _ in 0..n {
+            read_head(&mut stream).await?;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabc")
+                .await?;
+        }
+        Ok(true)
+    }
+    async fn get(port: u16, pool: &Pool) -> Result<(), crate::Error> {
+        let uri = format!("http://127.0.0.1:{}/", port);
+        let mut aw = Request::get(&uri).pool(pool).exec().await?;
+        assert_eq!(aw.text().await?, "abc", "wrong text");
+        Ok(())
+    }
+    #[test]
+    fn reuse_between_requests() {
+        block_on(async {
+            let (listener, port, _) = listen_somewhere().await?;
+            let t = spawn(server(listener, 2));
+            let pool = Pool::default();
+            get(port, &pool).await?;
+            get(port, &pool).await?;
+            assert!(t.await?, "not cool");
+            let stats = pool.stats();
+            assert_eq!((stats.hits, stats.misses), (1, 1), "{:?}", stats);
+            Ok(())
+        })
+        .unwrap();
+    }
+    /// answers one request per connection. Closes it, if `close`
+    async fn one_per_conn(listener: TcpListener, close: bool) -> std::io::Result<bool> {
+        let mut open = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().await?;
+            read_head(&mut stream).await?;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabc")
+                .await?;
+            if !close {
+                open.push(stream);
+            }
+        }
+        Ok(true)
+    }
+    #[test]
+    fn stale() {
+        block_on(async {
+            let (listener, port, _) = listen_somewhere().await?;
+            let t = spawn(one_per_conn(listener, true));
+            let pool = Pool::default();
+            get(port, &pool).await?;
+            get(port, &pool).await?;
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn expired() {
+        block_on(async {
+            let (listener, port, _) = listen_somewhere().await?;
+            let t = spawn(one_per_conn(listener, false));
+            let pool = Pool::new(PoolConfig::new().idle_timeout(Some(Duration::ZERO)));
+            get(port, &pool).await?;
+            get(port, &pool).await?;
+            assert!(t.await?, "not cool");
+            let stats = pool.stats();
+            assert_eq!((stats.hits, stats.misses), (0, 2), "{:?}", stats);
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn max_connections() {
+        let pool = Pool::new(PoolConfig::new().max_connections(1));
+        let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+        let mut first = Box::pin(pool.permit());
+        let Poll::Ready(first) = first.as_mut().poll(&mut cx) else {
+            panic!("no permit");
+        };
+        let mut second = Box::pin(pool.permit());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(pool.stats().open, 1);
+        drop(first);
+        assert!(second.as_mut().poll(&mut cx).is_ready());
+    }
+}
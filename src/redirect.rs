@@ -0,0 +1,409 @@
+//! Decide if and how redirects are followed.
+//!
+//! A [`Policy`] can be set per [`Request`](crate::Request::redirect)
+//! or per [`Session`](crate::Session::redirect).
+//! By default up to 10 redirects are followed.
+//!
+//! ```
+//! # use generic_async_http_client::{Request, Error, redirect::Policy};
+//! # async fn get() -> Result<(), Error> {
+//!     let req = Request::get("http://example.com/").redirect(Policy::same_origin(3));
+//!     let resp = req.exec().await?;
+//!     println!("{} via {:?}", resp.url(), resp.redirects());
+//! # Ok(())
+//! # }
+//! ```
+use std::{fmt::Debug, sync::Arc};
+
+/// Redirect policy
+#[derive(Clone)]
+pub struct Policy(Kind);
+
+#[derive(Clone)]
+enum Kind {
+    None,
+    Limited(usize),
+    SameOrigin(usize),
+    Custom(Arc<dyn Fn(&Attempt<'_>) -> bool + Send + Sync>),
+}
+
+impl Policy {
+    /// Never follow a redirect. 3xx responses are returned as they are
+    pub fn none() -> Policy {
+        Policy(Kind::None)
+    }
+    /// Follow up to `max` redirects
+    pub fn limited(max: usize) -> Policy {
+        Policy(Kind::Limited(max))
+    }
+    /// Follow up to `max` redirects, but only if they stay on the same scheme, host and port
+    pub fn same_origin(max: usize) -> Policy {
+        Policy(Kind::SameOrigin(max))
+    }
+    /// Let a closure decide if a redirect is followed
+    /// ```
+    /// # use generic_async_http_client::redirect::Policy;
+    /// let policy = Policy::custom(|attempt| {
+    ///     attempt.previous().len() < 5 && attempt.url().starts_with("https://")
+    /// });
+    /// ```
+    pub fn custom<F>(f: F) -> Policy
+    where
+        F: Fn(&Attempt<'_>) -> bool + Send + Sync + 'static,
+    {
+        Policy(Kind::Custom(Arc::new(f)))
+    }
+    pub(crate) fn is_none(&self) -> bool {
+        matches!(self.0, Kind::None | Kind::Limited(0) | Kind::SameOrigin(0))
+    }
+    pub(crate) fn follow(&self, attempt: &Attempt<'_>) -> bool {
+        match &self.0 {
+            Kind::None => false,
+            Kind::Limited(max) => attempt.previous.len() <= *max,
+            Kind::SameOrigin(max) => attempt.previous.len() <= *max && !attempt.is_cross_origin(),
+            Kind::Custom(f) => f(attempt),
+        }
+    }
+}
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::limited(10)
+    }
+}
+impl Debug for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Kind::None => f.write_str("None"),
+            Kind::Limited(max) => write!(f, "Limited({})", max),
+            Kind::SameOrigin(max) => write!(f, "SameOrigin({})", max),
+            Kind::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// A redirect that is about to be followed
+pub struct Attempt<'a> {
+    pub(crate) status: u16,
+    pub(crate) url: &'a str,
+    pub(crate) previous: &'a [String],
+}
+impl Attempt<'_> {
+    /// Status code of the redirect response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+    /// The (absolute) URL the redirect points to
+    pub fn url(&self) -> &str {
+        self.url
+    }
+    /// URLs requested so far. The last one is the URL that responded with the redirect
+    pub fn previous(&self) -> &[String] {
+        self.previous
+    }
+    /// `true` if scheme, host or port differ from the last request
+    pub fn is_cross_origin(&self) -> bool {
+        match self.previous.last() {
+            Some(last) => origin(last).is_none() || origin(last) != origin(self.url),
+            None => false,
+        }
+    }
+}
+
+/// split an absolute URL into scheme, authority and the rest (path and query)
+fn split(url: &str) -> Option<(&str, &str, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    Some((scheme, &rest[..end], &rest[end..]))
+}
+
+/// scheme, host and port of an absolute URL
+fn origin(url: &str) -> Option<(String, String, u16)> {
+    let (scheme, authority, _) = split(url)?;
+    let scheme = scheme.to_ascii_lowercase();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let (host, port) = match host_port.rfind(':') {
+        Some(i) if !host_port[i..].contains(']') => (&host_port[..i], Some(&host_port[i + 1..])),
+        _ => (host_port, None),
+    };
+    let port = match port.filter(|p| !p.is_empty()) {
+        Some(p) => p.parse().ok()?,
+        None => match scheme.as_str() {
+            "https" => 443,
+            "http" => 80,
+            _ => return None,
+        },
+    };
+    Some((scheme, host.to_ascii_lowercase(), port))
+}
+
+/// check if `url` starts with `scheme:`
+fn has_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(i) if i > 0 => {
+            let scheme = &url[..i];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+/// RFC 3986 5.2.4
+fn remove_dot_segments(path: &str) -> String {
+    let mut out: Vec<&str> = Vec::new();
+    let mut segments = path.split('/').peekable();
+    //path is absolute -> first segment is empty
+    segments.next();
+    while let Some(seg) = segments.next() {
+        let last = segments.peek().is_none();
+        match seg {
+            "." => {
+                if last {
+                    out.push("");
+                }
+            }
+            ".." => {
+                out.pop();
+                if last {
+                    out.push("");
+                }
+            }
+            s => out.push(s),
+        }
+    }
+    let mut p = String::with_capacity(path.len());
+    for seg in out {
+        p.push('/');
+        p.push_str(seg);
+    }
+    if p.is_empty() {
+        p.push('/');
+    }
+    p
+}
+
+/// Resolve the value of a `Location` header against the URL of the request (RFC 3986 5.2).
+/// The fragment is dropped
+pub(crate) fn resolve(base: &str, location: &str) -> Option<String> {
+    let location = location.trim();
+    let location = location.split('#').next().unwrap_or_default();
+    if has_scheme(location) {
+        return Some(location.to_string());
+    }
+    let (scheme, authority, rest) = split(base)?;
+    if let Some(loc) = location.strip_prefix("//") {
+        return Some(format!("{}://{}", scheme, loc));
+    }
+    let base_path = rest.split(['?', '#']).next().unwrap_or_default();
+    let (path, query) = match location.find('?') {
+        Some(i) => location.split_at(i),
+        None => (location, ""),
+    };
+    let path = if path.starts_with('/') {
+        remove_dot_segments(path)
+    } else if path.is_empty() {
+        if query.is_empty() {
+            //same document
+            let query = rest[base_path.len()..]
+                .split('#')
+                .next()
+                .unwrap_or_default();
+            return Some(format!("{}://{}{}{}", scheme, authority, base_path, query));
+        }
+        if base_path.is_empty() {
+            "/".to_string()
+        } else {
+            base_path.to_string()
+        }
+    } else {
+        let dir = match base_path.rfind('/') {
+            Some(i) => &base_path[..=i],
+            None => "/",
+        };
+        remove_dot_segments(&format!("{}{}", dir, path))
+    };
+    Some(format!("{}://{}{}{}", scheme, authority, path, query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn resolve_location() {
+        let base = "http://a/b/c/d;p?q";
+        //RFC 3986 5.4.1
+        for (loc, res) in [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q"),
+            ("g#s", "http://a/b/c/g"),
+            (";x", "http://a/b/c/;x"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            ("..g", "http://a/b/c/..g"),
+            ("./g/.", "http://a/b/c/g/"),
+            ("g/../h", "http://a/b/c/h"),
+        ] {
+            assert_eq!(resolve(base, loc).as_deref(), Some(res), "{}", loc);
+        }
+        assert_eq!(
+            resolve("https://a:8443", "b").as_deref(),
+            Some("https://a:8443/b")
+        );
+    }
+    #[test]
+    fn cross_origin() {
+        let previous = ["http://a/b".to_string()];
+        let a = |url| Attempt {
+            status: 302,
+            url,
+            previous: &previous,
+        };
+        assert!(!a("http://a:80/c").is_cross_origin());
+        assert!(!a("http://A/").is_cross_origin());
+        assert!(a("https://a/b").is_cross_origin());
+        assert!(a("http://a:8080/b").is_cross_origin());
+        assert!(a("http://b/b").is_cross_origin());
+        assert!(a("http://[::1]/b").is_cross_origin());
+        assert!(!Policy::same_origin(5).follow(&a("http://b/b")));
+        assert!(Policy::same_origin(5).follow(&a("http://a/c")));
+        assert!(!Policy::limited(0).follow(&a("http://a/c")));
+    }
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    #[test]
+    fn follow() {
+        use crate::tests::{
+            block_on, listen_somewhere, read_head, spawn, ReadExt, TcpListener, WriteExt,
+        };
+        async fn server(listener: TcpListener, host: String, port: u16) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("POST /a HTTP/1.1\r\n"), "{}", head);
+            let mut body = [0; 4];
+            stream.read_exact(&mut body).await?;
+            assert_eq!(&body, b"data");
+            stream
+                .write_all(
+                    b"HTTP/1.1 307 Temporary Redirect\r\nlocation: b\r\ncontent-length: 0\r\n\r\n",
+                )
+                .await?;
+
+            let (mut stream, _) = listener.accept().await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("POST /b HTTP/1.1\r\n"), "{}", head);
+            assert!(
+                head.contains("\r\ncontent-type: text/plain\r\n"),
+                "{}",
+                head
+            );
+            stream.read_exact(&mut body).await?;
+            assert_eq!(&body, b"data");
+            stream
+                .write_all(format!("HTTP/1.1 303 See Other\r\nlocation: http://{}:{}/c\r\ncontent-length: 0\r\n\r\n", host, port).as_bytes())
+                .await?;
+
+            let (mut stream, _) = listener.accept().await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("GET /c HTTP/1.1\r\n"), "{}", head);
+            assert!(!head.contains("content-type"), "{}", head);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\ndone")
+                .await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let uri = format!("http://{}:{}/", host, port);
+            let t = spawn(server(listener, host, port));
+            //a new connection for each request
+            let pool = crate::pool::Pool::new(crate::pool::PoolConfig::new().max_idle_per_host(0));
+            let mut resp = crate::Request::post(&format!("{}a", uri))
+                .pool(&pool)
+                .body("data")?
+                .set_header("Content-Type", "text/plain")?
+                .exec()
+                .await?;
+
+            assert_eq!(resp.status_code(), 200, "wrong status");
+            assert_eq!(resp.text().await?, "done", "wrong text");
+            assert_eq!(resp.url(), format!("{}c", uri));
+            assert_eq!(resp.redirects(), [format!("{}a", uri), format!("{}b", uri)]);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "mock")]
+    #[test]
+    fn policy() {
+        use crate::{Error, Mock, Request};
+        use futures::executor::block_on;
+        block_on(async {
+            Mock::update("GET", "http://example.com/", |r| {
+                r.set_response(301, "");
+                r.add_response_header("Location", "/a").unwrap();
+            });
+            Mock::update("GET", "http://example.com/a", |r| {
+                r.set_response(302, "");
+                r.add_response_header("Location", "http://other.com/")
+                    .unwrap();
+            });
+            Mock::update("GET", "http://other.com/", |r| {
+                r.set_response(200, "other");
+            });
+
+            let resp = Request::get("http://example.com/")
+                .redirect(Policy::none())
+                .exec()
+                .await?;
+            assert_eq!(resp.status_code(), 301);
+            assert!(resp.redirects().is_empty());
+
+            let resp = Request::get("http://example.com/")
+                .redirect(Policy::same_origin(10))
+                .exec()
+                .await?;
+            assert_eq!(resp.status_code(), 302);
+            assert_eq!(resp.url(), "http://example.com/a");
+
+            let resp = Request::get("http://example.com/")
+                .redirect(Policy::limited(1))
+                .exec()
+                .await?;
+            assert_eq!(resp.status_code(), 302);
+
+            let mut resp = Request::get("http://example.com/").exec().await?;
+            assert_eq!(resp.text().await?, "other");
+            assert_eq!(
+                resp.redirects(),
+                ["http://example.com/", "http://example.com/a"]
+            );
+
+            let resp = Request::get("http://example.com/")
+                .redirect(Policy::custom(|a| a.status() == 301))
+                .exec()
+                .await?;
+            assert_eq!(resp.status_code(), 302);
+            Result::<(), Error>::Ok(())
+        })
+        .unwrap();
+    }
+}
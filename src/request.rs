@@ -1,6 +1,15 @@
-use crate::{imp, Body, Error, HeaderName, HeaderValue, Response};
+use crate::{
+    imp, redirect,
+    response::{Meta, Responses},
+    timeout::{timeout, TimeoutKind},
+    Body, Error, HeaderName, HeaderValue, Multipart, Response, Timeouts,
+};
 use serde::Serialize;
-use std::{convert::TryInto, fmt::Debug};
+use std::{
+    convert::TryInto,
+    fmt::Debug,
+    time::{Duration, Instant},
+};
 
 /// Builds a HTTP request, poll it to query
 /// ```
@@ -13,35 +22,65 @@ use std::{convert::TryInto, fmt::Debug};
 /// ```
 ///
 /// Depending on the chosen implementation, `Request` implements `TryFrom<(TryInto<Method>, TryInto<Url>)>`.
-pub struct Request(pub(crate) imp::Req);
+pub struct Request(pub(crate) imp::Req, pub(crate) Options);
+
+/// Settings that are handled independent of the backend
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Options {
+    pub(crate) redirect: redirect::Policy,
+    pub(crate) timeouts: Timeouts,
+    #[cfg(feature = "cookies")]
+    pub(crate) cookie_jar: Option<crate::CookieJar>,
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        any(
+            feature = "rustls_byoc",
+            feature = "hyper_native_tls",
+            feature = "async_native_tls"
+        )
+    ))]
+    pub(crate) tls: Option<crate::TlsConfig>,
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        feature = "proxies"
+    ))]
+    pub(crate) proxy: Option<crate::tcp::proxy::Scoped>,
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    pub(crate) resolver: Option<crate::tcp::resolve::Scoped>,
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    pub(crate) ip_family: crate::resolve::IpFamily,
+    #[cfg(all(unix, any(feature = "use_hyper", feature = "use_async_h1")))]
+    pub(crate) unix_socket: Option<crate::tcp::UnixAddr>,
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    pub(crate) http2_prior_knowledge: bool,
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    pub(crate) http2: crate::Http2Config,
+    #[cfg(feature = "http3")]
+    pub(crate) http3: crate::Http3,
+}
 impl Request {
     //auth
-    //proxy - should be set by bin
-    //cookies
-    //timeout
-    //tls validation
-    //tls client certa
-    //session (ref + cookies)
     pub fn get(uri: &str) -> Request {
-        Request(imp::Req::get(uri))
+        Request(imp::Req::get(uri), Options::default())
     }
     pub fn post(uri: &str) -> Request {
-        Request(imp::Req::post(uri))
+        Request(imp::Req::post(uri), Options::default())
     }
     pub fn put(uri: &str) -> Request {
-        Request(imp::Req::put(uri))
+        Request(imp::Req::put(uri), Options::default())
     }
     pub fn delete(uri: &str) -> Request {
-        Request(imp::Req::delete(uri))
+        Request(imp::Req::delete(uri), Options::default())
     }
     pub fn head(uri: &str) -> Request {
-        Request(imp::Req::head(uri))
+        Request(imp::Req::head(uri), Options::default())
     }
     pub fn options(uri: &str) -> Request {
-        Request(imp::Req::options(uri))
+        Request(imp::Req::options(uri), Options::default())
     }
     pub fn new(meth: &str, uri: &str) -> Result<Request, Error> {
-        Ok(imp::Req::new(meth, uri).map(Request)?)
+        let req = imp::Req::new(meth, uri)?;
+        Ok(Request(req, Options::default()))
     }
     /// Add a JSON body to the request
     /// ```
@@ -134,36 +173,262 @@ impl Request {
 
         Ok(self)
     }
-    /*
-    TODO stream body
-    body(Body::from_reader)
-    */
-    //TODO multipart
+    /// Add a `multipart/form-data` body to the request. See [`Multipart`]
+    pub fn multipart(self, form: Multipart) -> Result<Self, Error> {
+        let content_type = form.content_type();
+        self.body(form.into_body())?
+            .set_header("Content-Type", content_type.as_str())
+    }
+
+    /// Send cookies from `jar` and store the ones the server sets in it.
+    /// A `Cookie` header set by hand is replaced by the one of the jar.
+    /// See [`CookieJar`](crate::CookieJar)
+    #[cfg(feature = "cookies")]
+    pub fn cookie_jar(mut self, jar: &crate::CookieJar) -> Self {
+        self.1.cookie_jar = Some(jar.clone());
+        self
+    }
+    /// Use `config` for HTTPS connections. See [`TlsConfig`](crate::TlsConfig).
+    ///
+    /// Only connections that were made with the same config (or a clone of it) are reused
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        any(
+            feature = "rustls_byoc",
+            feature = "hyper_native_tls",
+            feature = "async_native_tls"
+        )
+    ))]
+    pub fn tls(mut self, config: &crate::TlsConfig) -> Self {
+        self.1.tls = Some(config.clone());
+        self
+    }
+    /// Connect through `proxy` instead of the global one.
+    /// See [`proxy`](crate::proxy)
+    /// ```
+    /// # use generic_async_http_client::{Request, Error, proxy::NoProxy};
+    /// # use std::sync::Arc;
+    /// # async fn get() -> Result<(), Error> {
+    ///     Request::get("http://example.com/").proxy(Arc::new(NoProxy)).exec().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        feature = "proxies"
+    ))]
+    pub fn proxy(mut self, proxy: std::sync::Arc<dyn crate::proxy::Proxy>) -> Self {
+        self.1.proxy = Some(crate::tcp::proxy::Scoped(proxy));
+        self
+    }
+    /// Look up host names with `resolver` instead of the global one.
+    /// See [`resolve`](crate::resolve)
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    pub fn resolver(mut self, resolver: std::sync::Arc<dyn crate::resolve::Resolve>) -> Self {
+        self.1.resolver = Some(crate::tcp::resolve::Scoped(resolver));
+        self
+    }
+    /// Only connect to addresses of `family`.
+    /// By default, IPv6 and IPv4 addresses are raced
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    pub fn ip_family(mut self, family: crate::resolve::IpFamily) -> Self {
+        self.1.ip_family = family;
+        self
+    }
+    /// Connect to the Unix domain socket at `path` instead of the host of the URL.
+    /// The `Host` header is still taken from the URL
+    /// ```
+    /// # use generic_async_http_client::{Request, Error};
+    /// # async fn get() -> Result<(), Error> {
+    ///     Request::get("http://docker/v1.43/version")
+    ///         .unix_socket("/var/run/docker.sock")
+    ///         .exec()
+    ///         .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(unix, any(feature = "use_hyper", feature = "use_async_h1")))]
+    pub fn unix_socket(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.1.unix_socket = Some(crate::tcp::UnixAddr::Path(path.as_ref().to_path_buf()));
+        self
+    }
+    /// Connect to the socket `name` in the Linux abstract namespace, instead of the host of the URL.
+    /// `name` is without the leading NUL byte
+    #[cfg(all(
+        target_os = "linux",
+        any(feature = "use_hyper", feature = "use_async_h1")
+    ))]
+    pub fn abstract_socket(mut self, name: impl AsRef<[u8]>) -> Self {
+        self.1.unix_socket = Some(crate::tcp::UnixAddr::Abstract(name.as_ref().to_vec()));
+        self
+    }
+    /// Speak HTTP/2 on `http://` connections right away (h2c with prior knowledge),
+    /// instead of HTTP/1.1.
+    /// For `https://`, HTTP/2 is negotiated with ALPN anyway
+    /// ```
+    /// # use generic_async_http_client::{Request, Error};
+    /// # async fn get() -> Result<(), Error> {
+    ///     Request::get("http://grpc.internal:50051/")
+    ///         .http2_prior_knowledge()
+    ///         .exec()
+    ///         .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.1.http2_prior_knowledge = true;
+        self
+    }
+    /// Use `config` for new HTTP/2 connections. See [`Http2Config`](crate::Http2Config)
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    pub fn http2(mut self, config: crate::Http2Config) -> Self {
+        self.1.http2 = config;
+        self
+    }
+    /// Choose when to use HTTP/3 for `https://`. See [`Http3`](crate::Http3)
+    /// ```
+    /// # use generic_async_http_client::{Request, Http3, Error};
+    /// # async fn get() -> Result<(), Error> {
+    ///     Request::get("https://cloudflare-quic.com/")
+    ///         .http3(Http3::Only)
+    ///         .exec()
+    ///         .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, when: crate::Http3) -> Self {
+        self.1.http3 = when;
+        self
+    }
+    /// Reuse the connections of `pool` instead of the global one.
+    /// See [`pool`](crate::pool)
+    pub fn pool(mut self, pool: &crate::pool::Pool) -> Self {
+        self.0.set_pool(pool.clone());
+        self
+    }
+    /// Set how redirects are followed. See [`redirect::Policy`]
+    pub fn redirect(mut self, policy: redirect::Policy) -> Self {
+        self.1.redirect = policy;
+        self
+    }
+
+    /// Set a deadline for the whole request (including reading the response).
+    /// If it expires, [`Error::Timeout`] is returned
+    /// ```
+    /// # use generic_async_http_client::{Request, Error};
+    /// # use std::time::Duration;
+    /// # async fn get() -> Result<(), Error> {
+    ///     let req = Request::get("http://example.com/").timeout(Duration::from_secs(10));
+    ///     let text = req.exec().await?.text().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn timeout(mut self, total: Duration) -> Self {
+        self.1.timeouts.total = Some(total);
+        self
+    }
+    /// Set all [`Timeouts`] of the request
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.1.timeouts = timeouts;
+        self
+    }
 
     /// Send the request to the webserver
     pub async fn exec(self) -> Result<Response, Error> {
-        #[cfg(all(feature = "mock_tests", test))]
-        let r = if crate::Mock::uses_mock() {
-            Response(crate::Mock::check(self.0)?)
-        } else {
-            self.0.send_request().await?
-        };
-        #[cfg(not(all(feature = "mock_tests", test)))]
-        let r = self.0.send_request().await?;
-        //https://crates.io/crates/hreq
+        let total = self.1.timeouts.total;
+        let deadline = total.map(|t| Instant::now() + t);
+        //boxed, as the future of a whole request is quite big
+        let fut = Box::pin(self.exec_until(deadline));
+        timeout(total, TimeoutKind::Total, fut).await?
+    }
+    async fn exec_until(self, deadline: Option<Instant>) -> Result<Response, Error> {
+        let Request(mut req, options) = self;
+        let mut redirects: Vec<String> = Vec::new();
+        loop {
+            let url = req.url();
+            #[cfg(feature = "cookies")]
+            if let Some(jar) = &options.cookie_jar {
+                //the cookies of the previous hop might not match this url
+                req.remove_header("cookie");
+                let initiator = redirects.first().map(String::as_str);
+                if let Some(cookie) = jar.header(&url, initiator, &req.method()) {
+                    let value: HeaderValue = cookie.as_str().try_into()?;
+                    let name: HeaderName = "cookie".try_into()?;
+                    req.set_header(name.into(), value.into())?;
+                }
+            }
+            let next = if options.redirect.is_none() {
+                None
+            } else {
+                req.try_clone().await
+            };
+
+            #[cfg(feature = "mock")]
+            let resp = if crate::Mock::uses_mock() {
+                crate::Mock::check(req).await?
+            } else {
+                req.send_request(&options).await?
+            };
+            #[cfg(not(feature = "mock"))]
+            let resp = req.send_request(&options).await?;
+            let mut r = Response(resp, Meta::default());
+            //https://crates.io/crates/hreq
 
-        match r.status_code() {
-            100..300 => Ok(r),
-            300..400 => {
-                if let Some(loc) = r.header("Location").and_then(|l| l.try_into().ok()) {
-                    let _l: String = loc;
-                    //TODO redirect
+            #[cfg(feature = "cookies")]
+            if let Some(jar) = &options.cookie_jar {
+                if let Ok(set_cookie) = r.all_header("set-cookie") {
+                    jar.store(&url, set_cookie);
                 }
-                Ok(r)
             }
-            s @ 400..500 => Err(Error::HTTPClientErr(s, r)),
-            //s @ 500..600 => Err(Error::HTTPServerErr(s, r)),
-            s => Err(Error::HTTPServerErr(s, r)),
+
+            let status = r.status_code();
+            if let (Some(mut next), 301 | 302 | 303 | 307 | 308) = (next, status) {
+                if let Some(location) = r
+                    .header("Location")
+                    .and_then(|l| l.as_str().ok())
+                    .and_then(|l| redirect::resolve(&url, l))
+                {
+                    redirects.push(url.clone());
+                    let attempt = redirect::Attempt {
+                        status,
+                        url: &location,
+                        previous: &redirects,
+                    };
+                    if options.redirect.follow(&attempt) {
+                        let cross_origin = attempt.is_cross_origin();
+                        log::debug!("redirect {} to {}", status, location);
+                        if status == 303 && next.method() != "HEAD"
+                            || (status == 301 || status == 302) && next.method() == "POST"
+                        {
+                            next.change_to_get();
+                        }
+                        if cross_origin {
+                            next.remove_header("authorization");
+                            next.remove_header("cookie");
+                        }
+                        next.set_url(&location)?;
+                        //read the body, so that the connection can be reused
+                        let _ = r.0.bytes().await;
+                        req = next;
+                        continue;
+                    }
+                    redirects.pop();
+                }
+            }
+            r.1 = Meta {
+                url,
+                redirects,
+                deadline,
+            };
+
+            return match status {
+                100..400 => Ok(r),
+                s @ 400..500 => Err(Error::HTTPClientErr(s, r)),
+                //s @ 500..600 => Err(Error::HTTPServerErr(s, r)),
+                s => Err(Error::HTTPServerErr(s, r)),
+            };
         }
     }
 }
@@ -183,7 +448,7 @@ pub trait Requests: Debug {
     fn new(meth: &str, uri: &str) -> Result<Self, imp::Error>
     where
         Self: std::marker::Sized;
-    async fn send_request(self) -> Result<Response, imp::Error>
+    async fn send_request(self, options: &Options) -> Result<imp::Resp, imp::Error>
     where
         Self: std::marker::Sized;
     fn json<T: Serialize + ?Sized>(&mut self, json: &T) -> Result<(), imp::Error>;
@@ -200,6 +465,21 @@ pub trait Requests: Debug {
         name: imp::HeaderName,
         values: imp::HeaderValue,
     ) -> Result<(), imp::Error>;
+    /// reuse the connections of `pool` instead of the global one
+    fn set_pool(&mut self, pool: crate::pool::Pool);
+    /// Uppercase method
+    fn method(&self) -> String;
+    /// Full URL
+    fn url(&self) -> String;
+    /// Copy the request (including the body) to follow a redirect.
+    /// `None` if that is not possible
+    async fn try_clone(&mut self) -> Option<Self>
+    where
+        Self: std::marker::Sized;
+    fn set_url(&mut self, url: &str) -> Result<(), imp::Error>;
+    /// Make this a GET request without a body. HEAD requests stay HEAD
+    fn change_to_get(&mut self);
+    fn remove_header(&mut self, name: &str);
 }
 
 /*
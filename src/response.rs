@@ -1,14 +1,44 @@
-use crate::{imp, Error, HeaderName, HeaderValue};
+use crate::{
+    imp,
+    timeout::{timeout, TimeoutKind},
+    Error, HeaderName, HeaderValue,
+};
+use bytes::{Buf, Bytes};
+use futures::{stream::BoxStream, Stream, StreamExt, TryStreamExt};
 use serde::de::DeserializeOwned;
-use std::convert::TryInto;
+use std::{
+    convert::TryInto,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
 /// The response of a webserver.
 /// Headers and Status are available from the start,
 /// the body must be polled/awaited again
 ///
 /// Depending on the chosen implementation, `Response` implements `Into<http_types::Response>` or `Into<hyper::Response>`.
-pub struct Response(pub(crate) imp::Resp);
+pub struct Response(pub(crate) imp::Resp, pub(crate) Meta);
+
+/// Where a response came from
+#[derive(Debug, Default)]
+pub(crate) struct Meta {
+    pub(crate) url: String,
+    pub(crate) redirects: Vec<String>,
+    /// the total timeout expires here
+    pub(crate) deadline: Option<Instant>,
+}
 impl Response {
+    /// The URL of the final request, after all redirects were followed
+    pub fn url(&self) -> &str {
+        &self.1.url
+    }
+    /// URLs of the requests that returned a redirect leading to this response.
+    /// The original URL comes first
+    pub fn redirects(&self) -> &[String] {
+        &self.1.redirects
+    }
     /// Return the status code
     pub fn status_code(&self) -> u16 {
         self.0.status()
@@ -17,17 +47,24 @@ impl Response {
     pub fn status(&self) -> &str {
         self.0.status_str()
     }
+    /// time left until the total timeout expires
+    fn time_left(&self) -> Option<Duration> {
+        time_left(self.1.deadline)
+    }
     /// Return the Body as some type deserialized from JSON
     pub async fn json<D: DeserializeOwned>(&mut self) -> Result<D, Error> {
-        Ok(self.0.json().await?)
+        let left = self.time_left();
+        Ok(timeout(left, TimeoutKind::Total, self.0.json()).await??)
     }
     /// Return the whole Body as Bytes
     pub async fn content(&mut self) -> Result<Vec<u8>, Error> {
-        Ok(self.0.bytes().await?)
+        let left = self.time_left();
+        Ok(timeout(left, TimeoutKind::Total, self.0.bytes()).await??)
     }
     /// Return the whole Body as String
     pub async fn text(&mut self) -> Result<String, Error> {
-        Ok(self.0.string().await?)
+        let left = self.time_left();
+        Ok(timeout(left, TimeoutKind::Total, self.0.string()).await??)
     }
     /// If there are multiple values associated with the key, then the first one is returned.
     pub fn header(
@@ -51,12 +88,133 @@ impl Response {
     pub fn headers(&self) -> impl Iterator<Item = (&HeaderName, &HeaderValue)> {
         self.0.header_iter().map(|(n, v)| (n.into(), v.into()))
     }
+    /// The body in chunks, as they arrive
+    /// ```
+    /// # use generic_async_http_client::{Request, Error};
+    /// # use futures::TryStreamExt;
+    /// # async fn get() -> Result<(), Error> {
+    ///     let resp = Request::get("http://example.com/").exec().await?;
+    ///     let mut body = resp.bytes_stream();
+    ///     while let Some(chunk) = body.try_next().await? {
+    ///         println!("{} bytes", chunk.len());
+    ///     }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn bytes_stream(self) -> impl Stream<Item = Result<Bytes, Error>> + Send + 'static {
+        self.into_stream().map_err(Error::from)
+    }
+    /// Read the body, without buffering it completely. See [`BodyReader`]
+    pub fn into_reader(self) -> BodyReader {
+        BodyReader {
+            stream: self.into_stream(),
+            chunk: Bytes::new(),
+        }
+    }
+    /// stream of the body, that honors the total timeout
+    fn into_stream(self) -> BoxStream<'static, io::Result<Bytes>> {
+        let deadline = self.1.deadline;
+        let stream = self.0.into_stream();
+        if deadline.is_none() {
+            return stream;
+        }
+        futures::stream::try_unfold(stream, move |mut stream| async move {
+            let next = timeout(time_left(deadline), TimeoutKind::Total, stream.next()).await?;
+            Ok(next.transpose()?.map(|chunk| (chunk, stream)))
+        })
+        .boxed()
+    }
     /*
-    TODO cookie
     TODO encoding
-    TODO raw (impl Read and or Stream)
     */
 }
+/// time left until `deadline`
+fn time_left(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|d| d.saturating_duration_since(Instant::now()))
+}
+
+/// Reads the body of a [`Response`]. Created by [`Response::into_reader`].
+///
+/// It implements `AsyncRead` and `AsyncBufRead` of `futures` and,
+/// if hyper is used, the ones of `tokio`.
+/// ```
+/// # use generic_async_http_client::{Request, Error};
+/// # async fn download(mut file: async_std::fs::File) -> Result<(), Error> {
+///     let resp = Request::get("http://example.com/").exec().await?;
+///     futures::io::copy(resp.into_reader(), &mut file).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BodyReader {
+    stream: BoxStream<'static, io::Result<Bytes>>,
+    chunk: Bytes,
+}
+impl BodyReader {
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        while self.chunk.is_empty() {
+            match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.chunk = chunk,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => break,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(&self.chunk))
+    }
+    fn copy_to(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let n = match self.poll_chunk(cx) {
+            Poll::Ready(Ok(chunk)) => chunk.len().min(buf.len()),
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        self.chunk.copy_to_slice(&mut buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+}
+impl futures::AsyncRead for BodyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().copy_to(cx, buf)
+    }
+}
+impl futures::AsyncBufRead for BodyReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        self.get_mut().poll_chunk(cx)
+    }
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().chunk.advance(amt)
+    }
+}
+#[cfg(feature = "use_hyper")]
+impl tokio::io::AsyncRead for BodyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let n = match this.poll_chunk(cx) {
+            Poll::Ready(Ok(chunk)) => chunk.len().min(buf.remaining()),
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        buf.put_slice(&this.chunk[..n]);
+        this.chunk.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+#[cfg(feature = "use_hyper")]
+impl tokio::io::AsyncBufRead for BodyReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        self.get_mut().poll_chunk(cx)
+    }
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().chunk.advance(amt)
+    }
+}
 
 impl std::fmt::Debug for Response {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -71,7 +229,60 @@ pub trait Responses {
     async fn json<D: DeserializeOwned>(&mut self) -> Result<D, imp::Error>;
     async fn bytes(&mut self) -> Result<Vec<u8>, imp::Error>;
     async fn string(&mut self) -> Result<String, imp::Error>;
+    /// the body in chunks, as they arrive
+    fn into_stream(self) -> BoxStream<'static, io::Result<Bytes>>;
     fn get_header(&self, name: imp::HeaderName) -> Option<&imp::HeaderValue>;
     fn get_headers(&self, name: imp::HeaderName) -> impl Iterator<Item = &imp::HeaderValue>;
     fn header_iter(&self) -> impl Iterator<Item = (&imp::HeaderName, &imp::HeaderValue)>;
 }
+
+#[cfg(all(test, any(feature = "use_hyper", feature = "use_async_h1")))]
+mod tests {
+    use super::*;
+    use crate::tests::{block_on, listen_somewhere, read_head, spawn, TcpListener, WriteExt};
+    use crate::Request;
+
+    async fn server(listener: TcpListener) -> io::Result<bool> {
+        let (mut stream, _) = listener.accept().await?;
+        read_head(&mut stream).await?;
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n")
+            .await?;
+        stream.flush().await?;
+        stream.write_all(b"2\r\nde\r\n0\r\n\r\n").await?;
+        Ok(true)
+    }
+    #[test]
+    fn bytes_stream() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let resp = Request::get(&format!("http://{}:{}/", host, port))
+                .exec()
+                .await?;
+            let chunks: Vec<Bytes> = resp.bytes_stream().try_collect().await?;
+            assert_eq!(chunks.concat(), b"abcde");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn into_reader() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let resp = Request::get(&format!("http://{}:{}/", host, port))
+                .exec()
+                .await?;
+            let mut body = Vec::new();
+            futures::AsyncReadExt::read_to_end(&mut resp.into_reader(), &mut body).await?;
+            assert_eq!(body, b"abcde");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
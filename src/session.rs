@@ -1,75 +1,251 @@
-
-use crate::{Request, Error, HeaderName, HeaderValue};
-use std::convert::TryInto;
+use crate::{
+    pool::Pool,
+    redirect,
+    request::{Options, Requests},
+    Error, HeaderName, HeaderValue, Request, Timeouts,
+};
 use std::collections::HashMap;
+use std::convert::TryInto;
+#[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+use std::sync::Arc;
 
 /// A helper to perform multiple associated requests.
-/// (Session-)Cookies will be handled
+///
+/// Connections are kept alive and reused for later requests to the same host.
+/// Each session has its own [`Pool`], unless [`Session::pool`] is set.
+/// Headers set on the session are added to all requests done with it.
+/// ```
+/// # use generic_async_http_client::{Session, Error};
+/// # async fn get() -> Result<(), Error> {
+///     let session = Session::new().set_header("User-Agent", "generic_async_http_client v0.7")?;
+///     let mut resp = session.get("http://example.com/a").exec().await?;
+///     let _a = resp.text().await?;
+///     //reuses the connection
+///     let resp = session.get("http://example.com/b").exec().await?;
+/// # Ok(())
+/// # }
+/// ```
 pub struct Session {
     headers: HashMap<HeaderName, HeaderValue>,
-    #[cfg(feature = "cookies")]
-    cookies: CookieStore
+    pool: Pool,
+    options: Options,
 }
 impl Session {
-    #[cfg(feature = "cookies")]
-    pub fn cookies(&self) {
-        /*CookieStore::parse(
-            &mut self,
-            cookie_str: &str,
-            request_url: &Url*/
-    }
+    /// A new session. With the `cookies` feature, it has an empty [`CookieJar`](crate::CookieJar)
     pub fn new() -> Session {
-        Session{
-            headers: HashMap::new()
+        #[allow(unused_mut)]
+        let mut options = Options::default();
+        #[cfg(feature = "cookies")]
+        {
+            options.cookie_jar = Some(crate::CookieJar::new());
         }
+        Session {
+            headers: HashMap::new(),
+            pool: Pool::default(),
+            options,
+        }
+    }
+    /// Use `jar` for all requests done with this session
+    #[cfg(feature = "cookies")]
+    pub fn cookie_jar(mut self, jar: &crate::CookieJar) -> Self {
+        self.options.cookie_jar = Some(jar.clone());
+        self
+    }
+    /// Use `config` for all HTTPS connections of this session.
+    /// See [`TlsConfig`](crate::TlsConfig)
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        any(
+            feature = "rustls_byoc",
+            feature = "hyper_native_tls",
+            feature = "async_native_tls"
+        )
+    ))]
+    pub fn tls(mut self, config: &crate::TlsConfig) -> Self {
+        self.options.tls = Some(config.clone());
+        self
+    }
+    /// Connect through `proxy` instead of the global one, for all requests done with this session.
+    /// See [`proxy`](crate::proxy)
+    #[cfg(all(
+        any(feature = "use_hyper", feature = "use_async_h1"),
+        feature = "proxies"
+    ))]
+    pub fn proxy(mut self, proxy: Arc<dyn crate::proxy::Proxy>) -> Self {
+        self.options.proxy = Some(crate::tcp::proxy::Scoped(proxy));
+        self
+    }
+    /// Look up host names with `resolver` instead of the global one, for all requests done with this session.
+    /// See [`resolve`](crate::resolve)
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    pub fn resolver(mut self, resolver: Arc<dyn crate::resolve::Resolve>) -> Self {
+        self.options.resolver = Some(crate::tcp::resolve::Scoped(resolver));
+        self
+    }
+    /// Only connect to addresses of `family`, for all requests done with this session
+    #[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+    pub fn ip_family(mut self, family: crate::resolve::IpFamily) -> Self {
+        self.options.ip_family = family;
+        self
+    }
+    /// Connect to the Unix domain socket at `path` for all requests done with this session.
+    /// See [`Request::unix_socket`]
+    #[cfg(all(unix, any(feature = "use_hyper", feature = "use_async_h1")))]
+    pub fn unix_socket(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.options.unix_socket = Some(crate::tcp::UnixAddr::Path(path.as_ref().to_path_buf()));
+        self
+    }
+    /// Connect to the socket `name` in the Linux abstract namespace for all requests done with this session.
+    /// See [`Request::abstract_socket`]
+    #[cfg(all(
+        target_os = "linux",
+        any(feature = "use_hyper", feature = "use_async_h1")
+    ))]
+    pub fn abstract_socket(mut self, name: impl AsRef<[u8]>) -> Self {
+        self.options.unix_socket = Some(crate::tcp::UnixAddr::Abstract(name.as_ref().to_vec()));
+        self
+    }
+    /// Speak HTTP/2 on `http://` connections right away, for all requests done with this session.
+    /// See [`Request::http2_prior_knowledge`]
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.options.http2_prior_knowledge = true;
+        self
+    }
+    /// Use `config` for new HTTP/2 connections of this session.
+    /// See [`Http2Config`](crate::Http2Config)
+    #[cfg(all(feature = "use_hyper", feature = "http2"))]
+    pub fn http2(mut self, config: crate::Http2Config) -> Self {
+        self.options.http2 = config;
+        self
+    }
+    /// Choose when requests of this session use HTTP/3. See [`Http3`](crate::Http3).
+    ///
+    /// With [`Http3::AltSvc`](crate::Http3::AltSvc), what is learned is kept in the pool of the session
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, when: crate::Http3) -> Self {
+        self.options.http3 = when;
+        self
+    }
+    /// Keep the connections of this session in `pool`, to share them with other sessions
+    pub fn pool(mut self, pool: &Pool) -> Self {
+        self.pool = pool.clone();
+        self
+    }
+    /// Set how redirects are followed by all requests done with this session.
+    /// See [`redirect::Policy`]
+    pub fn redirect(mut self, policy: redirect::Policy) -> Self {
+        self.options.redirect = policy;
+        self
+    }
+    /// Set the [`Timeouts`] of all requests done with this session
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.options.timeouts = timeouts;
+        self
     }
     /// Add a single header to all request done with this session
-    pub fn set_header(mut self,
-        name: impl TryInto<HeaderName, Error = Error>,
-        value: impl TryInto<HeaderValue, Error = Error>) -> Result<Self, Error> {
-
-        let val :HeaderValue = value.try_into()?;
-        let name :HeaderName = name.try_into()?;
+    /// If the map did have this key present, the new value is associated with the key
+    pub fn set_header<N, V, E1, E2>(mut self, name: N, value: V) -> Result<Self, Error>
+    where
+        N: TryInto<HeaderName, Error = E1>,
+        V: TryInto<HeaderValue, Error = E2>,
+        Error: From<E1>,
+        Error: From<E2>,
+    {
+        let val: HeaderValue = value.try_into()?;
+        let name: HeaderName = name.try_into()?;
         self.headers.insert(name, val);
-        
+
         Ok(self)
     }
-    fn add_session_data(&self, req: Request) -> Request {
+    fn add_session_data(&self, mut req: Request) -> Request {
         for (n, v) in self.headers.iter() {
-            req.set_header(n.clone(), v.clone());
-        }
-        #[cfg(feature = "cookies")]
-        {
-            let c = self.cookies.get_request_values(url)
-            .map(|(n,v)| format!("{}={}", n, v))
-            .collect::<Vec<_>>()
-            .join("; ");
-            if c.len() > 0 {
-                req.set_header("Cookie", c);
-            }
+            req.0
+                .set_header(n.clone().into(), v.clone().into())
+                .expect("header was checked by Session::set_header");
         }
+        req.0.set_pool(self.pool.clone());
+        req.1 = self.options.clone();
         req
     }
 
-    pub fn get(&mut self, uri: &str) -> Request {
+    pub fn get(&self, uri: &str) -> Request {
         self.add_session_data(Request::get(uri))
     }
-    pub fn post(&mut self, uri: &str) -> Request {
+    pub fn post(&self, uri: &str) -> Request {
         self.add_session_data(Request::post(uri))
     }
-    pub fn put(&mut self, uri: &str) -> Request {
+    pub fn put(&self, uri: &str) -> Request {
         self.add_session_data(Request::put(uri))
     }
-    pub fn delete(&mut self, uri: &str) -> Request {
+    pub fn delete(&self, uri: &str) -> Request {
         self.add_session_data(Request::delete(uri))
     }
-    pub fn head(&mut self, uri: &str) -> Request {
+    pub fn head(&self, uri: &str) -> Request {
         self.add_session_data(Request::head(uri))
     }
-    pub fn options(&mut self, uri: &str) -> Request {
+    pub fn options(&self, uri: &str) -> Request {
         self.add_session_data(Request::options(uri))
     }
-    pub fn request(&mut self, meth: &str, uri: &str) -> Result<Request, Error> {
-        Request::new(meth, uri).map(|r|self.add_session_data(r))
+    pub fn request(&self, meth: &str, uri: &str) -> Result<Request, Error> {
+        Request::new(meth, uri).map(|r| self.add_session_data(r))
+    }
+}
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
     }
-}
\ No newline at end of file
+}
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("headers", &self.headers)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, any(feature = "use_hyper", feature = "use_async_h1")))]
+mod tests {
+    use super::*;
+    use crate::tests::{block_on, listen_somewhere, read_head, spawn, TcpListener, WriteExt};
+
+    #[test]
+    fn reuse_connection() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("GET /a HTTP/1.1\r\n"), "{}", head);
+            assert!(head.contains("\r\nuser-agent: test\r\n"), "{}", head);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\na")
+                .await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("POST /b HTTP/1.1\r\n"), "{}", head);
+            assert!(head.contains("\r\nuser-agent: test\r\n"), "{}", head);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\nb")
+                .await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let session = Session::new().set_header("User-Agent", "test")?;
+            let mut aw = session
+                .get(&format!("http://{}:{}/a", host, port))
+                .exec()
+                .await?;
+            assert_eq!(aw.text().await?, "a", "wrong text");
+            let mut aw = session
+                .post(&format!("http://{}:{}/b", host, port))
+                .exec()
+                .await?;
+            assert_eq!(aw.text().await?, "b", "wrong text");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
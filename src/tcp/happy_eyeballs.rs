@@ -0,0 +1,125 @@
+//! Happy Eyeballs v2 (RFC 8305)
+use futures::future::{select, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+#[cfg(feature = "use_async_h1")]
+use async_std::net::TcpStream;
+#[cfg(feature = "use_hyper")]
+use tokio::net::TcpStream;
+
+/// Time until the next address is tried, while the previous attempt is still pending
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connect to one of `addrs`. A new attempt is started every [`CONNECTION_ATTEMPT_DELAY`]
+/// or as soon as the previous one failed. The first connection wins.
+pub(crate) async fn connect(addrs: &[SocketAddr]) -> io::Result<TcpStream> {
+    let mut pending = interleave(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+    loop {
+        if let Some(addr) = pending.next() {
+            log::trace!("connecting to {}", addr);
+            attempts.push(TcpStream::connect(addr));
+        }
+        let done = if pending.len() == 0 {
+            attempts.next().await
+        } else {
+            #[cfg(feature = "use_hyper")]
+            let delay = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY);
+            #[cfg(feature = "use_async_h1")]
+            let delay = async_std::task::sleep(CONNECTION_ATTEMPT_DELAY);
+            match select(attempts.next(), std::pin::pin!(delay)).await {
+                Either::Left((done, _)) => done,
+                Either::Right(_) => continue,
+            }
+        };
+        match done {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(e)) => last_err = Some(e),
+            None => {
+                return Err(last_err.unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no address to connect to")
+                }))
+            }
+        }
+    }
+}
+
+/// Alternate between the address families, starting with the family of the first address
+fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return Vec::new();
+    };
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .iter()
+        .copied()
+        .partition(|a| a.is_ipv6() == first.is_ipv6());
+    let mut out = Vec::with_capacity(addrs.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return out,
+            (a, b) => out.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{block_on, listen_somewhere, spawn, TcpListener};
+    use std::time::Instant;
+
+    #[test]
+    fn interleaved() {
+        let a: Vec<SocketAddr> = ["[::1]:1", "[::2]:1", "[::3]:1", "1.0.0.1:1", "1.0.0.2:1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let i = interleave(&a);
+        assert_eq!(i, vec![a[0], a[3], a[1], a[4], a[2]]);
+        let i = interleave(&a[3..]);
+        assert_eq!(i, a[3..].to_vec());
+        assert!(interleave(&[]).is_empty());
+    }
+    #[test]
+    fn race() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            listener.accept().await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, port, _) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+            let start = Instant::now();
+            //TEST-NET-1 never answers (or is unreachable)
+            let addrs = [
+                "192.0.2.1:80".parse()?,
+                SocketAddr::new([127, 0, 0, 1].into(), port),
+            ];
+            let stream = connect(&addrs).await?;
+            assert_eq!(stream.peer_addr()?.port(), port);
+            assert!(start.elapsed() < Duration::from_secs(2), "waited too long");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn all_fail() {
+        block_on(async {
+            //nothing listens here anymore
+            let (listener, port, _) = listen_somewhere().await?;
+            drop(listener);
+            let addrs = [SocketAddr::new([127, 0, 0, 1].into(), port)];
+            let e = connect(&addrs).await.unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+            Ok(())
+        })
+        .unwrap();
+    }
+}
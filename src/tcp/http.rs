@@ -1,12 +1,19 @@
+use super::proxy::{Credentials, ProxyError};
+use super::resolve::{self, Resolve};
 #[cfg(feature = "use_async_h1")]
 use async_std::{
-    io::prelude::{ReadExt, WriteExt},
+    io::{
+        prelude::{ReadExt, WriteExt},
+        Read as AsyncRead, Write as AsyncWrite,
+    },
     net::TcpStream,
 };
 use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 #[cfg(feature = "use_hyper")]
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
 };
 
@@ -15,43 +22,194 @@ pub async fn connect_via_http_prx(
     port: u16,
     phost: &str,
     pport: u16,
-) -> io::Result<TcpStream> {
-    let mut socket = TcpStream::connect((phost, pport)).await?;
+    auth: Option<&Credentials>,
+    resolver: &dyn Resolve,
+) -> io::Result<Prefixed<TcpStream>> {
+    let socket = resolve::connect(resolver, phost, pport).await?;
+    tunnel(socket, host, port, auth).await
+}
+
+/// Ask the proxy on the other end of `socket` for a tunnel to `host`
+pub async fn tunnel<S>(
+    mut socket: S,
+    host: &str,
+    port: u16,
+    auth: Option<&Credentials>,
+) -> io::Result<Prefixed<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let auth = match auth {
+        Some(c) => format!("Proxy-Authorization: {}\r\n", c.basic()),
+        None => String::new(),
+    };
     let buf = format!(
         "CONNECT {0}:{1} HTTP/1.1\r\n\
          Host: {0}:{1}\r\n\
          {2}\
          \r\n",
-        host,
-        port,
-        "" //TODO Auth
+        host, port, auth
     )
     .into_bytes();
     socket.write_all(&buf).await?;
-    let mut buffer = [0; 40];
-    let r = socket.read(&mut buffer).await?;
-
-    let mut read = &buffer[..r];
-    if r > 12 && (read.starts_with(b"HTTP/1.1 200") || read.starts_with(b"HTTP/1.0 200")) {
-        loop {
-            if read.ends_with(b"\r\n\r\n") {
-                return Ok(socket);
+    socket.flush().await?;
+    let prefix = read_connect_response(&mut socket).await?;
+    Ok(Prefixed {
+        prefix,
+        pos: 0,
+        inner: socket,
+    })
+}
+
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Read the answer to CONNECT.
+///
+/// Returns what the proxy sent through the tunnel along with the response head
+async fn read_connect_response<S>(socket: &mut S) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut head = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    loop {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "proxy closed the connection",
+            ));
+        }
+        head.extend_from_slice(&chunk[..n]);
+
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut resp = httparse::Response::new(&mut headers);
+        match resp.parse(&head) {
+            Ok(httparse::Status::Complete(len)) => {
+                match resp.code {
+                    Some(200..=299) => {}
+                    Some(407) => return Err(ProxyError::AuthRequired.into()),
+                    code => {
+                        return Err(ProxyError::Refused(
+                            code.unwrap_or_default(),
+                            resp.reason.unwrap_or_default().to_string(),
+                        )
+                        .into())
+                    }
+                }
+                return Ok(head.split_off(len));
             }
-            // else read more
-            let r = socket.read(&mut buffer).await?;
-            if r == 0 {
-                break;
+            Ok(httparse::Status::Partial) if head.len() >= MAX_HEAD => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "proxy response head too large",
+                ))
             }
-            read = &buffer[..r];
+            Ok(httparse::Status::Partial) => {}
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
+/// A tunnel, that returns what the proxy sent along with its response first
+#[derive(Debug)]
+pub struct Prefixed<S> {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: S,
+}
+/// Nothing sent through yet
+impl<S> From<S> for Prefixed<S> {
+    fn from(inner: S) -> Self {
+        Prefixed {
+            prefix: Vec::new(),
+            pos: 0,
+            inner,
+        }
+    }
+}
+impl<S> Prefixed<S> {
+    /// The connection itself, if nothing was sent through the tunnel yet
+    pub fn into_inner(self) -> Result<S, Prefixed<S>> {
+        if self.pos < self.prefix.len() {
+            Err(self)
+        } else {
+            Ok(self.inner)
+        }
+    }
+}
+#[cfg(feature = "use_hyper")]
+impl<S: AsyncRead + Unpin> AsyncRead for Prefixed<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos < this.prefix.len() {
+            let n = (this.prefix.len() - this.pos).min(buf.remaining());
+            buf.put_slice(&this.prefix[this.pos..this.pos + n]);
+            this.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+#[cfg(feature = "use_hyper")]
+impl<S: AsyncWrite + Unpin> AsyncWrite for Prefixed<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+#[cfg(feature = "use_async_h1")]
+impl<S: AsyncRead + Unpin> AsyncRead for Prefixed<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.pos < this.prefix.len() {
+            let n = (this.prefix.len() - this.pos).min(buf.len());
+            buf[..n].copy_from_slice(&this.prefix[this.pos..this.pos + n]);
+            this.pos += n;
+            return Poll::Ready(Ok(n));
         }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+#[cfg(feature = "use_async_h1")]
+impl<S: AsyncWrite + Unpin> AsyncWrite for Prefixed<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
     }
-    Err(io::Error::new(io::ErrorKind::InvalidData, host.to_string()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tests::{assert_stream, block_on, listen_somewhere, spawn, TcpListener};
+    use crate::resolve::SystemResolver;
+    use crate::tests::{assert_stream, block_on, listen_somewhere, spawn, ReadExt, TcpListener};
     #[test]
     fn http_proxy() {
         async fn server(listener: TcpListener) -> std::io::Result<bool> {
@@ -71,7 +229,8 @@ mod tests {
             let (listener, pport, phost) = listen_somewhere().await?;
             let t = spawn(server(listener));
 
-            let mut stream = connect_via_http_prx("host", 1234, &phost, pport).await?;
+            let mut stream =
+                connect_via_http_prx("host", 1234, &phost, pport, None, &SystemResolver).await?;
             stream.write_all(b"n0ice").await?;
 
             assert!(t.await?, "not cool");
@@ -79,4 +238,130 @@ mod tests {
         })
         .unwrap();
     }
+    /// the proxy sends its response and the first bytes of the tunnel at once
+    #[test]
+    fn http_proxy_early_data() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(
+                &mut stream,
+                b"CONNECT host:1234 HTTP/1.1\r\nHost: host:1234\r\n\r\n",
+            )
+            .await?;
+            stream
+                .write_all(b"HTTP/1.1 200 Connected\r\n\r\n220 ready\r\n")
+                .await?;
+            assert_stream(&mut stream, b"n0ice").await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, pport, phost) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let mut stream =
+                connect_via_http_prx("host", 1234, &phost, pport, None, &SystemResolver).await?;
+            let mut greeting = [0; 11];
+            stream.read_exact(&mut greeting).await?;
+            assert_eq!(&greeting, b"220 ready\r\n");
+            stream.write_all(b"n0ice").await?;
+
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn http_proxy_auth() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(
+                &mut stream,
+                b"CONNECT host:1234 HTTP/1.1\r\nHost: host:1234\r\n\r\n",
+            )
+            .await?;
+            stream
+                .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                .await?;
+
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(
+                &mut stream,
+                b"CONNECT host:1234 HTTP/1.1\r\nHost: host:1234\r\n\
+                Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n",
+            )
+            .await?;
+            stream.write_all(b"HTTP/1.1 200 Connected\r\n\r\n").await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, pport, phost) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let e = connect_via_http_prx("host", 1234, &phost, pport, None, &SystemResolver)
+                .await
+                .unwrap_err();
+            assert_eq!(ProxyError::of(&e), Some(ProxyError::AuthRequired));
+            let auth = Credentials {
+                user: "user".to_string(),
+                pass: "pass".to_string(),
+            };
+            connect_via_http_prx("host", 1234, &phost, pport, Some(&auth), &SystemResolver).await?;
+
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn http_proxy_head() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(
+                &mut stream,
+                b"CONNECT host:1234 HTTP/1.1\r\nHost: host:1234\r\n\r\n",
+            )
+            .await?;
+            //the head in small pieces, followed by tunnel data
+            for b in b"HTTP/1.0 200 Connection established\r\nProxy-Agent: test\r\n".chunks(7) {
+                stream.write_all(b).await?;
+                stream.flush().await?;
+            }
+            stream.write_all(b"\r\nhello").await?;
+            assert_stream(&mut stream, b"n0ice").await?;
+
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(
+                &mut stream,
+                b"CONNECT host:1234 HTTP/1.1\r\nHost: host:1234\r\n\r\n",
+            )
+            .await?;
+            stream
+                .write_all(b"HTTP/1.1 403 Forbidden\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, pport, phost) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let mut stream =
+                connect_via_http_prx("host", 1234, &phost, pport, None, &SystemResolver).await?;
+            let mut hello = [0; 5];
+            stream.read_exact(&mut hello).await?;
+            assert_eq!(&hello, b"hello");
+            stream.write_all(b"n0ice").await?;
+
+            let e = connect_via_http_prx("host", 1234, &phost, pport, None, &SystemResolver)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                ProxyError::of(&e),
+                Some(ProxyError::Refused(403, "Forbidden".to_string()))
+            );
+
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
 }
@@ -0,0 +1,195 @@
+use std::net::IpAddr;
+
+/// Hosts that are reached without a proxy, as set in `NO_PROXY`.
+///
+/// A comma-separated list of
+/// - `*` for all hosts
+/// - domain names, matching the domain and all subdomains.
+///   With a leading `.` or `*.`, only subdomains match
+/// - IP addresses. IPv6 addresses can be in brackets
+/// - CIDR blocks like `10.0.0.0/8` or `fd00::/8`
+///
+/// Names and addresses can be followed by `:port` to only match that port.
+/// Names are compared case-insensitive. No DNS lookups are done
+#[derive(Debug, Default, Clone)]
+pub(crate) struct NoProxyRules {
+    rules: Vec<Rule>,
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Rule {
+    Any,
+    Domain {
+        name: String,
+        subdomains_only: bool,
+        port: Option<u16>,
+    },
+    Ip {
+        addr: IpAddr,
+        port: Option<u16>,
+    },
+    Cidr {
+        net: IpAddr,
+        prefix: u8,
+    },
+}
+
+impl NoProxyRules {
+    pub(crate) fn parse(list: &str) -> NoProxyRules {
+        let rules = list
+            .split(',')
+            .map(|e| e.trim().to_ascii_lowercase())
+            .filter(|e| !e.is_empty())
+            .filter_map(|e| {
+                let r = Rule::parse(&e);
+                if r.is_none() {
+                    log::warn!("ignoring NO_PROXY entry {:?}", e);
+                }
+                r
+            })
+            .collect();
+        NoProxyRules { rules }
+    }
+    /// Should `host` be reached without a proxy
+    pub(crate) fn matches(&self, host: &str, port: u16) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let host = host.to_ascii_lowercase();
+        let host = host.trim_end_matches('.');
+        let host = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host);
+        let ip = host.parse::<IpAddr>().ok();
+        self.rules.iter().any(|r| r.matches(host, ip, port))
+    }
+}
+
+impl Rule {
+    fn parse(e: &str) -> Option<Rule> {
+        if e == "*" {
+            return Some(Rule::Any);
+        }
+        if let Some((net, prefix)) = e.split_once('/') {
+            let net = net.parse::<IpAddr>().ok()?;
+            let prefix = prefix.parse::<u8>().ok()?;
+            let max = if net.is_ipv4() { 32 } else { 128 };
+            if prefix > max {
+                return None;
+            }
+            return Some(Rule::Cidr { net, prefix });
+        }
+        if let Ok(addr) = e.parse::<IpAddr>() {
+            return Some(Rule::Ip { addr, port: None });
+        }
+        if let Some(bracketed) = e.strip_prefix('[') {
+            let (addr, rest) = bracketed.split_once(']')?;
+            let port = match rest {
+                "" => None,
+                p => Some(p.strip_prefix(':')?.parse::<u16>().ok()?),
+            };
+            return Some(Rule::Ip {
+                addr: addr.parse().ok()?,
+                port,
+            });
+        }
+        let (host, port) = match e.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port.parse::<u16>().ok()?)),
+            None => (e, None),
+        };
+        if let Ok(addr) = host.parse::<IpAddr>() {
+            return Some(Rule::Ip { addr, port });
+        }
+        let (name, subdomains_only) =
+            match host.strip_prefix("*.").or_else(|| host.strip_prefix('.')) {
+                Some(name) => (name, true),
+                None => (host, false),
+            };
+        let name = name.trim_end_matches('.');
+        if name.is_empty() || name.contains(['*', ':', '/']) {
+            return None;
+        }
+        Some(Rule::Domain {
+            name: name.to_string(),
+            subdomains_only,
+            port,
+        })
+    }
+    fn matches(&self, host: &str, ip: Option<IpAddr>, port: u16) -> bool {
+        let port_ok = |p: &Option<u16>| p.is_none_or(|p| p == port);
+        match self {
+            Rule::Any => true,
+            Rule::Ip { addr, port: p } => ip == Some(*addr) && port_ok(p),
+            Rule::Cidr { net, prefix } => ip.is_some_and(|ip| in_cidr(ip, *net, *prefix)),
+            Rule::Domain {
+                name,
+                subdomains_only,
+                port: p,
+            } => {
+                if !port_ok(p) || ip.is_some() {
+                    return false;
+                }
+                let sub = host.len() > name.len()
+                    && host.ends_with(name.as_str())
+                    && host.as_bytes()[host.len() - name.len() - 1] == b'.';
+                sub || (!subdomains_only && host == name)
+            }
+        }
+    }
+}
+
+fn in_cidr(ip: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn domains() {
+        let r = NoProxyRules::parse("Example.com, .internal.corp,*.svc , localhost:8080");
+        assert!(r.matches("example.com", 80));
+        assert!(r.matches("API.example.COM.", 443));
+        assert!(!r.matches("notexample.com", 80));
+        assert!(r.matches("api.internal.corp", 80));
+        assert!(!r.matches("internal.corp", 80));
+        assert!(r.matches("a.b.svc", 80));
+        assert!(!r.matches("svc", 80));
+        assert!(r.matches("localhost", 8080));
+        assert!(!r.matches("localhost", 80));
+        assert!(!NoProxyRules::parse("").matches("localhost", 80));
+        assert!(NoProxyRules::parse("*").matches("anything", 80));
+    }
+    #[test]
+    fn addresses() {
+        let r = NoProxyRules::parse("10.0.0.0/8,192.168.1.1:8080,::1,[fe80::1]:443,fd00::/8");
+        assert!(r.matches("10.1.2.3", 80));
+        assert!(!r.matches("11.0.0.1", 80));
+        assert!(r.matches("192.168.1.1", 8080));
+        assert!(!r.matches("192.168.1.1", 80));
+        assert!(r.matches("[::1]", 80));
+        assert!(r.matches("::1", 80));
+        assert!(r.matches("[fe80::1]", 443));
+        assert!(!r.matches("[fe80::1]", 80));
+        assert!(r.matches("[fd12:3456::1]", 80));
+        assert!(!r.matches("[fe12::1]", 80));
+        //no DNS
+        assert!(!r.matches("ten.example", 80));
+        assert!(NoProxyRules::parse("0.0.0.0/0").matches("1.2.3.4", 1));
+    }
+    #[test]
+    fn invalid() {
+        let r = NoProxyRules::parse("10.0.0.0/33,[::1,host:port,*.,a*b");
+        assert!(r.rules.is_empty(), "{:?}", r);
+    }
+}
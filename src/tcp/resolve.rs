@@ -0,0 +1,291 @@
+//! Name resolution.
+//!
+//! Host names are resolved with [`SystemResolver`], unless another [`Resolve`] is set
+//! globally with [`set_resolver`], for a [`Session`](crate::Session::resolver)
+//! or for a [`Request`](crate::Request::resolver).
+//!
+//! The host name is still used for TLS and the `Host` header:
+//! ```
+//! # use generic_async_http_client::{Request, Error, resolve::StaticResolver};
+//! # use std::sync::Arc;
+//! # async fn get() -> Result<(), Error> {
+//!     let staging = StaticResolver::new().add("api.example.com", 443, &["10.0.0.7".parse().unwrap()]);
+//!     Request::get("https://api.example.com/")
+//!         .resolver(Arc::new(staging))
+//!         .exec()
+//!         .await?;
+//! # Ok(())
+//! # }
+//! ```
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, PoisonError, RwLock};
+
+#[cfg(feature = "use_async_h1")]
+use async_std::net::{TcpStream, ToSocketAddrs};
+#[cfg(feature = "use_hyper")]
+use tokio::net::TcpStream;
+
+/// Trait to implement custom name resolution
+#[async_trait]
+pub trait Resolve: Send + Sync {
+    /// All addresses of `host`, with `port` set
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Sets the global resolver
+pub fn set_resolver(resolver: Arc<dyn Resolve>) {
+    *GLOBAL_RESOLVER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Some(resolver);
+}
+/// Returns the global resolver
+pub fn resolver() -> Arc<dyn Resolve> {
+    match &*GLOBAL_RESOLVER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+    {
+        Some(r) => r.clone(),
+        None => Arc::new(SystemResolver),
+    }
+}
+static GLOBAL_RESOLVER: RwLock<Option<Arc<dyn Resolve>>> = RwLock::new(None);
+
+/// A resolver for some requests, that is used instead of the global one
+#[derive(Clone)]
+pub(crate) struct Scoped(pub(crate) Arc<dyn Resolve>);
+impl std::fmt::Debug for Scoped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Resolve")
+    }
+}
+
+/// The resolver of the operating system.
+/// Lookups are done on a thread for blocking operations, not on the executor
+pub struct SystemResolver;
+#[async_trait]
+impl Resolve for SystemResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        #[cfg(feature = "use_hyper")]
+        let addrs = tokio::net::lookup_host((host, port)).await?;
+        #[cfg(feature = "use_async_h1")]
+        let addrs = (host, port).to_socket_addrs().await?;
+        Ok(addrs.collect())
+    }
+}
+
+/// Fixed addresses for some hosts, like curl's `--resolve host:port:addr`.
+///
+/// All other hosts are resolved by the fallback, [`SystemResolver`] by default
+pub struct StaticResolver {
+    /// port `None` matches all ports
+    entries: HashMap<(String, Option<u16>), Vec<IpAddr>>,
+    fallback: Arc<dyn Resolve>,
+}
+impl StaticResolver {
+    pub fn new() -> StaticResolver {
+        StaticResolver {
+            entries: HashMap::new(),
+            fallback: Arc::new(SystemResolver),
+        }
+    }
+    /// `host` resolves to `addrs`, if connecting to `port`
+    pub fn add(mut self, host: &str, port: u16, addrs: &[IpAddr]) -> Self {
+        self.entries
+            .insert((host.to_ascii_lowercase(), Some(port)), addrs.to_vec());
+        self
+    }
+    /// `host` resolves to `addrs` for all ports
+    pub fn add_all_ports(mut self, host: &str, addrs: &[IpAddr]) -> Self {
+        self.entries
+            .insert((host.to_ascii_lowercase(), None), addrs.to_vec());
+        self
+    }
+    /// Resolve all other hosts with `resolver`
+    pub fn fallback(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.fallback = resolver;
+        self
+    }
+}
+impl Default for StaticResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[async_trait]
+impl Resolve for StaticResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let host = host.to_ascii_lowercase();
+        let addrs = self
+            .entries
+            .get(&(host.clone(), Some(port)))
+            .or_else(|| self.entries.get(&(host.clone(), None)));
+        match addrs {
+            Some(addrs) => Ok(addrs.iter().map(|ip| SocketAddr::new(*ip, port)).collect()),
+            None => self.fallback.resolve(&host, port).await,
+        }
+    }
+}
+
+/// The IP versions to connect with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IpFamily {
+    /// Race IPv6 and IPv4 addresses (Happy Eyeballs)
+    #[default]
+    Both,
+    V4Only,
+    V6Only,
+}
+impl IpFamily {
+    fn allows(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpFamily::Both => true,
+            IpFamily::V4Only => addr.is_ipv4(),
+            IpFamily::V6Only => addr.is_ipv6(),
+        }
+    }
+}
+
+/// Drops the addresses of `inner` that are not of `family`
+pub(crate) struct Filtered {
+    pub(crate) inner: Arc<dyn Resolve>,
+    pub(crate) family: IpFamily,
+}
+impl Filtered {
+    /// The resolver and IP family chosen for a request
+    pub(crate) fn of(options: &crate::request::Options) -> Filtered {
+        Filtered {
+            inner: match &options.resolver {
+                Some(scoped) => scoped.0.clone(),
+                None => resolver(),
+            },
+            family: options.ip_family,
+        }
+    }
+}
+#[async_trait]
+impl Resolve for Filtered {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let mut addrs = self.inner.resolve(host, port).await?;
+        addrs.retain(|a| self.family.allows(a));
+        Ok(addrs)
+    }
+}
+
+/// Connect to `host`. IP addresses are used as they are, names are resolved by `resolver`.
+/// If there are multiple addresses, they are raced
+pub(crate) async fn connect(
+    resolver: &dyn Resolve,
+    host: &str,
+    port: u16,
+) -> io::Result<TcpStream> {
+    let addrs = lookup(resolver, host, port).await?;
+    super::happy_eyeballs::connect(&addrs).await
+}
+/// The addresses of `host`. Never empty
+pub(crate) async fn lookup(
+    resolver: &dyn Resolve,
+    host: &str,
+    port: u16,
+) -> io::Result<Vec<SocketAddr>> {
+    let literal = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    if let Ok(ip) = literal.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    let addrs = resolver.resolve(host, port).await?;
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Could not resolve {}", host),
+        ));
+    }
+    log::trace!("{} resolved to {:?}", host, addrs);
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{block_on, listen_somewhere, read_head, spawn, TcpListener, WriteExt};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn overrides() {
+        block_on(async {
+            let lo = IpAddr::V4(Ipv4Addr::LOCALHOST);
+            let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7));
+            let r = StaticResolver::new()
+                .add("API.example.com", 443, &[other])
+                .add_all_ports("api.example.com", &[lo]);
+            let a = r.resolve("api.example.com", 443).await?;
+            assert_eq!(a, vec![SocketAddr::new(other, 443)]);
+            let a = r.resolve("Api.Example.com", 80).await?;
+            assert_eq!(a, vec![SocketAddr::new(lo, 80)]);
+            //fallback
+            let a = r.resolve("localhost", 80).await?;
+            assert!(a.iter().all(|a| a.ip().is_loopback()), "{:?}", a);
+            //literals skip the resolver
+            let a = lookup(&r, "[::1]", 80).await?;
+            assert_eq!(a, vec!["[::1]:80".parse()?]);
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn family() {
+        block_on(async {
+            let both = StaticResolver::new()
+                .add_all_ports("dual.example", &["::1".parse()?, "127.0.0.1".parse()?]);
+            let v4 = Filtered {
+                inner: Arc::new(both),
+                family: IpFamily::V4Only,
+            };
+            let a = v4.resolve("dual.example", 80).await?;
+            assert_eq!(a, vec!["127.0.0.1:80".parse()?]);
+            let v6 = Filtered {
+                inner: Arc::new(v4),
+                family: IpFamily::V6Only,
+            };
+            let e = lookup(&v6, "dual.example", 80).await.unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::NotFound);
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn request_resolver() {
+        async fn server(listener: TcpListener, port: u16) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            let head = read_head(&mut stream).await?;
+            assert!(head.starts_with("GET /r HTTP/1.1\r\n"), "{}", head);
+            let host = format!("\r\nhost: api.example.com:{}\r\n", port);
+            assert!(head.to_lowercase().contains(&host), "{}", head);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabc")
+                .await?;
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, port, _) = listen_somewhere().await?;
+            let t = spawn(server(listener, port));
+            let r =
+                StaticResolver::new().add("api.example.com", port, &[Ipv4Addr::LOCALHOST.into()]);
+
+            let uri = format!("http://api.example.com:{}/r", port);
+            let req = crate::Request::get(&uri).resolver(Arc::new(r));
+            //not through a proxy from the environment
+            #[cfg(feature = "proxies")]
+            let req = req.proxy(Arc::new(crate::proxy::NoProxy));
+            let mut aw = req.exec().await?;
+            assert_eq!(aw.text().await?, "abc", "wrong text");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+use super::proxy::ProxyError;
+use super::resolve::{self, Resolve};
+#[cfg(feature = "use_async_h1")]
+use async_std::{
+    io::prelude::{ReadExt, WriteExt},
+    net::TcpStream,
+};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+#[cfg(feature = "use_hyper")]
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// SOCKS4, or SOCKS4a if `dns_via_prx`
+pub async fn connect_via_socks4_prx(
+    host: &str,
+    port: u16,
+    phost: &str,
+    pport: u16,
+    dns_via_prx: bool,
+    user_id: &str,
+    resolver: &dyn Resolve,
+) -> io::Result<TcpStream> {
+    if user_id.contains('\0') || host.contains('\0') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socks4 fields can not contain NUL",
+        ));
+    }
+    let mut buf = Vec::with_capacity(10 + user_id.len() + host.len());
+    buf.push(4_u8);
+    buf.push(1); //connect
+    buf.extend_from_slice(&port.to_be_bytes());
+
+    let ip = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => Some(ip),
+        _ if dns_via_prx => None,
+        _ => {
+            let a = resolve::lookup(resolver, host, port)
+                .await?
+                .into_iter()
+                .find_map(|a| match a.ip() {
+                    IpAddr::V4(ip) => Some(ip),
+                    IpAddr::V6(_) => None,
+                })
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no IPv4 address for the host")
+                })?;
+            Some(a)
+        }
+    };
+    match ip {
+        Some(ip) => {
+            buf.extend_from_slice(&ip.octets());
+            buf.extend_from_slice(user_id.as_bytes());
+            buf.push(0);
+        }
+        None => {
+            //4a: an invalid IP tells the proxy to look for the name
+            buf.extend_from_slice(&Ipv4Addr::new(0, 0, 0, 1).octets());
+            buf.extend_from_slice(user_id.as_bytes());
+            buf.push(0);
+            buf.extend_from_slice(host.as_bytes());
+            buf.push(0);
+        }
+    }
+
+    let mut socket = resolve::connect(resolver, phost, pport).await?;
+    socket.write_all(&buf).await?;
+    let mut resp = [0u8; 8]; //version status port ip
+    socket.read_exact(&mut resp).await?;
+    match resp {
+        [0, 90, ..] => Ok(socket),
+        //identd could not confirm the user-id
+        [0, 92, ..] | [0, 93, ..] => Err(ProxyError::AuthRequired.into()),
+        [0, 91, ..] => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "socks request rejected",
+        )),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "socks error")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::SystemResolver;
+    use crate::tests::{assert_stream, block_on, listen_somewhere, spawn, TcpListener};
+
+    #[test]
+    fn socks4a() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+
+            assert_stream(
+                &mut stream,
+                b"\x04\x01\x12\x34\0\0\0\x01me\0host\0", //version connect port ip user-id host
+            )
+            .await?;
+            stream.write_all(b"\0\x5a\0\0\0\0\0\0").await?; //granted
+            assert_stream(&mut stream, b"n0ice").await?;
+            stream.write_all(b"indeed").await?;
+
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, pport, phost) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let mut stream =
+                connect_via_socks4_prx("host", 0x1234, &phost, pport, true, "me", &SystemResolver)
+                    .await?;
+            stream.write_all(b"n0ice").await?;
+            assert_stream(&mut stream, b"indeed").await?;
+
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn socks4_ip4() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+
+            assert_stream(
+                &mut stream,
+                b"\x04\x01\x12\x34\x7f\0\0\x01\0", //version connect port ip (no) user-id
+            )
+            .await?;
+            stream.write_all(b"\0\x5a\0\0\0\0\0\0").await?; //granted
+            assert_stream(&mut stream, b"n0ice").await?;
+
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(&mut stream, b"\x04\x01\x12\x34\x7f\0\0\x01who\0").await?;
+            stream.write_all(b"\0\x5d\0\0\0\0\0\0").await?; //user-id mismatch
+
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(&mut stream, b"\x04\x01\x12\x34\x7f\0\0\x01\0").await?;
+            stream.write_all(b"\0\x5b\0\0\0\0\0\0").await?; //rejected
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, pport, phost) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            //resolved locally
+            let mut stream = connect_via_socks4_prx(
+                "localhost",
+                0x1234,
+                &phost,
+                pport,
+                false,
+                "",
+                &SystemResolver,
+            )
+            .await?;
+            stream.write_all(b"n0ice").await?;
+
+            let e = connect_via_socks4_prx(
+                "127.0.0.1",
+                0x1234,
+                &phost,
+                pport,
+                true,
+                "who",
+                &SystemResolver,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(ProxyError::of(&e), Some(ProxyError::AuthRequired));
+
+            let e = connect_via_socks4_prx(
+                "127.0.0.1",
+                0x1234,
+                &phost,
+                pport,
+                true,
+                "",
+                &SystemResolver,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -1,10 +1,12 @@
+use super::proxy::{Credentials, ProxyError};
+use super::resolve::{self, Resolve};
 #[cfg(feature = "use_async_h1")]
 use async_std::{
     io::prelude::{ReadExt, WriteExt},
     net::TcpStream,
 };
 use std::io;
-use std::net::{IpAddr, ToSocketAddrs};
+use std::net::IpAddr;
 #[cfg(feature = "use_hyper")]
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -17,6 +19,8 @@ pub async fn connect_via_socks_prx(
     phost: &str,
     pport: u16,
     dns_via_prx: bool,
+    auth: Option<&Credentials>,
+    resolver: &dyn Resolve,
 ) -> io::Result<TcpStream> {
     let mut buf = Vec::with_capacity(22);
     buf.push(5_u8);
@@ -48,11 +52,8 @@ pub async fn connect_via_socks_prx(
             }
         }
     } else {
-        let a = (host, port)
-            .to_socket_addrs()?
-            .next()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not resolve the host"))?;
-        match a.ip() {
+        let a = resolve::lookup(resolver, host, port).await?;
+        match a[0].ip() {
             IpAddr::V4(ip) => {
                 buf.push(1);
                 buf.extend_from_slice(&ip.octets())
@@ -64,14 +65,19 @@ pub async fn connect_via_socks_prx(
         }
     }
     buf.extend_from_slice(&port.to_be_bytes());
-    let mut socket = TcpStream::connect((phost, pport)).await?;
-    socket.write_all(b"\x05\x01\0").await?; //client auth methods: [no auth]
-    let mut auth = [0, 0];
-    socket.read_exact(&mut auth).await?;
-    if auth[0] == 5 && auth[1] == 0 {
-        //proxy wants no auth
+    let mut socket = resolve::connect(resolver, phost, pport).await?;
+    if auth.is_some() {
+        socket.write_all(b"\x05\x02\0\x02").await?; //client auth methods: [no auth, user/pass]
     } else {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "socks error"));
+        socket.write_all(b"\x05\x01\0").await?; //client auth methods: [no auth]
+    }
+    let mut method = [0, 0];
+    socket.read_exact(&mut method).await?;
+    match (method, auth) {
+        ([5, 0], _) => {} //proxy wants no auth
+        ([5, 2], Some(auth)) => user_pass_auth(&mut socket, auth).await?,
+        ([5, 0xff], _) => return Err(ProxyError::AuthRequired.into()), //no acceptable method
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "socks error")),
     }
     socket.write_all(&buf).await?;
     let bytes_read = socket.read(&mut buf[..5]).await?;
@@ -104,9 +110,33 @@ pub async fn connect_via_socks_prx(
     }
 }
 
+/// RFC 1929
+async fn user_pass_auth(socket: &mut TcpStream, auth: &Credentials) -> io::Result<()> {
+    if auth.user.len() > 255 || auth.pass.len() > 255 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socks credentials too long",
+        ));
+    }
+    let mut buf = Vec::with_capacity(3 + auth.user.len() + auth.pass.len());
+    buf.push(1); //version
+    buf.push(auth.user.len() as u8);
+    buf.extend_from_slice(auth.user.as_bytes());
+    buf.push(auth.pass.len() as u8);
+    buf.extend_from_slice(auth.pass.as_bytes());
+    socket.write_all(&buf).await?;
+    let mut status = [0, 0];
+    socket.read_exact(&mut status).await?;
+    if status[1] != 0 {
+        return Err(ProxyError::AuthRequired.into());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::resolve::SystemResolver;
     use crate::tests::{assert_stream, block_on, listen_somewhere, spawn, TcpListener};
 
     #[test]
@@ -135,7 +165,9 @@ mod tests {
             let (listener, pport, phost) = listen_somewhere().await?;
             let t = spawn(server(listener));
 
-            let mut stream = connect_via_socks_prx("host", 0x1234, &phost, pport, true).await?;
+            let mut stream =
+                connect_via_socks_prx("host", 0x1234, &phost, pport, true, None, &SystemResolver)
+                    .await?;
             stream.write_all(b"n0ice").await?;
             assert_stream(&mut stream, b"indeed").await?;
 
@@ -171,8 +203,16 @@ mod tests {
             let (listener, pport, phost) = listen_somewhere().await?;
             let t = spawn(server(listener));
 
-            let mut stream =
-                connect_via_socks_prx("127.0.0.1", 0x1234, &phost, pport, true).await?;
+            let mut stream = connect_via_socks_prx(
+                "127.0.0.1",
+                0x1234,
+                &phost,
+                pport,
+                true,
+                None,
+                &SystemResolver,
+            )
+            .await?;
             stream.write_all(b"n0ice").await?;
 
             assert!(t.await?, "not cool");
@@ -207,7 +247,9 @@ mod tests {
             let (listener, pport, phost) = listen_somewhere().await?;
             let t = spawn(server(listener));
 
-            let mut stream = connect_via_socks_prx("::1", 0x1234, &phost, pport, true).await?;
+            let mut stream =
+                connect_via_socks_prx("::1", 0x1234, &phost, pport, true, None, &SystemResolver)
+                    .await?;
             stream.write_all(b"n0ice").await?;
 
             assert!(t.await?, "not cool");
@@ -215,4 +257,72 @@ mod tests {
         })
         .unwrap();
     }
+    #[test]
+    fn socks5_auth() {
+        async fn server(listener: TcpListener) -> std::io::Result<bool> {
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(&mut stream, b"\x05\x02\0\x02").await?; //[no auth, user/pass]
+            stream.write_all(b"\x05\x02").await?; //proxy wants user/pass
+            assert_stream(&mut stream, b"\x01\x04user\x05wrong").await?;
+            stream.write_all(b"\x01\x01").await?; //failure
+
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(&mut stream, b"\x05\x02\0\x02").await?;
+            stream.write_all(b"\x05\x02").await?;
+            assert_stream(&mut stream, b"\x01\x04user\x04pass").await?;
+            stream.write_all(b"\x01\0").await?; //success
+            assert_stream(&mut stream, b"\x05\x01\0\x03\x04host\x12\x34").await?;
+            stream.write_all(b"\x05\0\0\x03\x04host\x12\x34").await?;
+            assert_stream(&mut stream, b"n0ice").await?;
+
+            let (mut stream, _) = listener.accept().await?;
+            assert_stream(&mut stream, b"\x05\x01\0").await?; //[no auth]
+            stream.write_all(b"\x05\xff").await?; //no acceptable method
+            Ok(true)
+        }
+        block_on(async {
+            let (listener, pport, phost) = listen_somewhere().await?;
+            let t = spawn(server(listener));
+
+            let mut auth = Credentials {
+                user: "user".to_string(),
+                pass: "wrong".to_string(),
+            };
+            let e = connect_via_socks_prx(
+                "host",
+                0x1234,
+                &phost,
+                pport,
+                true,
+                Some(&auth),
+                &SystemResolver,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(ProxyError::of(&e), Some(ProxyError::AuthRequired));
+
+            auth.pass = "pass".to_string();
+            let mut stream = connect_via_socks_prx(
+                "host",
+                0x1234,
+                &phost,
+                pport,
+                true,
+                Some(&auth),
+                &SystemResolver,
+            )
+            .await?;
+            stream.write_all(b"n0ice").await?;
+
+            let e =
+                connect_via_socks_prx("host", 0x1234, &phost, pport, true, None, &SystemResolver)
+                    .await
+                    .unwrap_err();
+            assert_eq!(ProxyError::of(&e), Some(ProxyError::AuthRequired));
+
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
 }
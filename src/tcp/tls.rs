@@ -0,0 +1,702 @@
+use super::TlsConnector;
+use crate::Error;
+use std::{
+    io,
+    sync::{Arc, OnceLock},
+};
+
+#[cfg(all(feature = "rustls_byoc", feature = "use_async_h1"))]
+use futures_rustls::rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    AlertDescription, ClientConfig, Error as RustlsError, RootCertStore,
+};
+#[cfg(all(feature = "rustls_byoc", feature = "use_hyper"))]
+use tokio_rustls::rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    AlertDescription, ClientConfig, Error as RustlsError, RootCertStore,
+};
+#[cfg(feature = "rustls_byoc")]
+use webpki_roots::TLS_SERVER_ROOTS;
+
+#[cfg(feature = "rustls_byoc")]
+type Cert = CertificateDer<'static>;
+#[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+type Cert = native_tls::Certificate;
+/// certificate chain and private key
+#[cfg(feature = "rustls_byoc")]
+type ClientCert = Arc<(Vec<Cert>, PrivateKeyDer<'static>)>;
+#[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+type ClientCert = native_tls::Identity;
+
+/// Settings for HTTPS connections.
+/// Attach it with [`Request::tls`](crate::Request::tls) or [`Session::tls`](crate::Session::tls).
+///
+/// By default, the built-in roots are trusted:
+/// [webpki-roots](https://crates.io/crates/webpki-roots) with rustls
+/// and the system store with native-tls.
+///
+/// The TLS connector is built once and shared by all clones.
+/// ```
+/// # use generic_async_http_client::{Request, TlsConfig, Error};
+/// # async fn get(ca: &[u8]) -> Result<(), Error> {
+///     let tls = TlsConfig::new().add_root_pem(ca)?.built_in_roots(false);
+///     Request::get("https://internal.example.com/").tls(&tls).exec().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TlsConfig {
+    roots: Vec<Cert>,
+    built_in_roots: bool,
+    native_roots: bool,
+    client_cert: Option<ClientCert>,
+    connector: Arc<OnceLock<TlsConnector>>,
+    /// no ALPN, as CONNECT is HTTP/1.1
+    #[cfg(feature = "proxies")]
+    proxy_connector: Arc<OnceLock<TlsConnector>>,
+    /// ALPN h3
+    #[cfg(feature = "http3")]
+    quic: Arc<OnceLock<quinn::ClientConfig>>,
+}
+impl TlsConfig {
+    pub fn new() -> TlsConfig {
+        TlsConfig {
+            roots: Vec::new(),
+            built_in_roots: true,
+            native_roots: false,
+            client_cert: None,
+            connector: Arc::default(),
+            #[cfg(feature = "proxies")]
+            proxy_connector: Arc::default(),
+            #[cfg(feature = "http3")]
+            quic: Arc::default(),
+        }
+    }
+    /// Trust all certificates in `pem`
+    pub fn add_root_pem(mut self, pem: &[u8]) -> Result<Self, Error> {
+        #[cfg(feature = "rustls_byoc")]
+        let certs = CertificateDer::pem_slice_iter(pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        #[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+        let certs = native_tls::Certificate::stack_from_pem(pem).map_err(invalid)?;
+        if certs.is_empty() {
+            return Err(invalid("no certificate in PEM").into());
+        }
+        for cert in certs {
+            self.add_root(cert)?;
+        }
+        Ok(self.changed())
+    }
+    /// Trust the DER encoded certificate `der`
+    pub fn add_root_der(mut self, der: &[u8]) -> Result<Self, Error> {
+        #[cfg(feature = "rustls_byoc")]
+        let cert = CertificateDer::from(der.to_vec());
+        #[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+        let cert = native_tls::Certificate::from_der(der).map_err(invalid)?;
+        self.add_root(cert)?;
+        Ok(self.changed())
+    }
+    /// Also trust the certificates of the operating system.
+    ///
+    /// With native-tls, these are the built-in roots
+    pub fn native_roots(mut self) -> Self {
+        self.native_roots = true;
+        self.changed()
+    }
+    /// Trust the built-in roots (the default).
+    /// Disable it to only trust the roots added to this config
+    pub fn built_in_roots(mut self, enable: bool) -> Self {
+        self.built_in_roots = enable;
+        self.changed()
+    }
+    /// Authenticate with the certificate chain in `cert` and the private key in `key`.
+    /// The key can be PKCS#8, SEC1 or RSA (PKCS#1)
+    #[cfg(feature = "rustls_byoc")]
+    pub fn client_cert_pem(self, cert: &[u8], key: &[u8]) -> Result<Self, Error> {
+        let chain = CertificateDer::pem_slice_iter(cert)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let key = PrivateKeyDer::from_pem_slice(key).map_err(invalid)?;
+        self.client_cert(chain, key)
+    }
+    /// Like [`TlsConfig::client_cert_pem`], but DER encoded.
+    /// `chain` starts with the client certificate
+    #[cfg(feature = "rustls_byoc")]
+    pub fn client_cert_der(self, chain: &[&[u8]], key: &[u8]) -> Result<Self, Error> {
+        let chain = chain
+            .iter()
+            .map(|c| CertificateDer::from(c.to_vec()))
+            .collect();
+        let key = PrivateKeyDer::try_from(key).map_err(invalid)?.clone_key();
+        self.client_cert(chain, key)
+    }
+    #[cfg(feature = "rustls_byoc")]
+    fn client_cert(mut self, chain: Vec<Cert>, key: PrivateKeyDer<'static>) -> Result<Self, Error> {
+        if chain.is_empty() {
+            return Err(invalid("no client certificate").into());
+        }
+        //fail now, if the key is not supported
+        ClientConfig::builder()
+            .with_root_certificates(RootCertStore::empty())
+            .with_client_auth_cert(chain.clone(), key.clone_key())
+            .map_err(invalid)?;
+        self.client_cert = Some(Arc::new((chain, key)));
+        Ok(self.changed())
+    }
+    /// Authenticate with the PKCS#12 archive `der`, that is encrypted with `password`
+    #[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+    pub fn client_cert_pkcs12(mut self, der: &[u8], password: &str) -> Result<Self, Error> {
+        let id = native_tls::Identity::from_pkcs12(der, password).map_err(invalid)?;
+        self.client_cert = Some(id);
+        Ok(self.changed())
+    }
+    fn add_root(&mut self, cert: Cert) -> io::Result<()> {
+        #[cfg(feature = "rustls_byoc")]
+        RootCertStore::empty().add(cert.clone()).map_err(invalid)?;
+        self.roots.push(cert);
+        Ok(())
+    }
+    /// settings changed, the connector needs to be rebuilt
+    fn changed(mut self) -> Self {
+        self.connector = Arc::default();
+        #[cfg(feature = "proxies")]
+        {
+            self.proxy_connector = Arc::default();
+        }
+        #[cfg(feature = "http3")]
+        {
+            self.quic = Arc::default();
+        }
+        self
+    }
+    /// Clones share it, until they are changed
+    pub(crate) fn identity(&self) -> Arc<dyn std::any::Any + Send + Sync> {
+        self.connector.clone()
+    }
+    /// The connector for this config. It is built on first use
+    pub(crate) fn connector(&self) -> io::Result<&TlsConnector> {
+        if let Some(c) = self.connector.get() {
+            return Ok(c);
+        }
+        let c = self.build(true)?;
+        Ok(self.connector.get_or_init(|| c))
+    }
+    /// The connector for `https://` proxies
+    #[cfg(feature = "proxies")]
+    pub(crate) fn proxy_connector(&self) -> io::Result<&TlsConnector> {
+        if let Some(c) = self.proxy_connector.get() {
+            return Ok(c);
+        }
+        let c = self.build(false)?;
+        Ok(self.proxy_connector.get_or_init(|| c))
+    }
+    /// The QUIC config for HTTP/3. It is built on first use
+    #[cfg(feature = "http3")]
+    pub(crate) fn quic_config(&self) -> io::Result<quinn::ClientConfig> {
+        if let Some(c) = self.quic.get() {
+            return Ok(c.clone());
+        }
+        let mut config = self.client_config()?;
+        config.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(config).map_err(invalid)?;
+        let c = quinn::ClientConfig::new(Arc::new(crypto));
+        Ok(self.quic.get_or_init(|| c).clone())
+    }
+    #[cfg(feature = "rustls_byoc")]
+    fn build(&self, alpn: bool) -> io::Result<TlsConnector> {
+        let mut config = self.client_config()?;
+        if alpn {
+            #[cfg(all(feature = "use_hyper", feature = "http2"))]
+            config.alpn_protocols.push(b"h2".to_vec());
+            config.alpn_protocols.push(b"http/1.1".to_vec());
+        }
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+    /// roots and client certificate, without ALPN
+    #[cfg(feature = "rustls_byoc")]
+    fn client_config(&self) -> io::Result<ClientConfig> {
+        let mut root_store = RootCertStore::empty();
+        if self.built_in_roots {
+            root_store.extend(TLS_SERVER_ROOTS.iter().cloned());
+        }
+        if self.native_roots {
+            let native = rustls_native_certs::load_native_certs();
+            for e in native.errors {
+                log::warn!("loading system certificates: {}", e);
+            }
+            let (added, ignored) = root_store.add_parsable_certificates(native.certs);
+            log::debug!("{} system certificates, {} ignored", added, ignored);
+        }
+        for cert in &self.roots {
+            root_store.add(cert.clone()).map_err(invalid)?;
+        }
+        if root_store.is_empty() {
+            return Err(invalid("no trusted root certificates"));
+        }
+
+        let config = ClientConfig::builder().with_root_certificates(root_store);
+        Ok(match &self.client_cert {
+            Some(c) => config
+                .with_client_auth_cert(c.0.clone(), c.1.clone_key())
+                .map_err(invalid)?,
+            None => config.with_no_client_auth(),
+        })
+    }
+    #[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+    #[cfg_attr(not(feature = "http2"), allow(unused_variables))]
+    fn build(&self, alpn: bool) -> io::Result<TlsConnector> {
+        let mut builder = native_tls::TlsConnector::builder();
+        for cert in &self.roots {
+            builder.add_root_certificate(cert.clone());
+        }
+        if let Some(id) = &self.client_cert {
+            builder.identity(id.clone());
+        }
+        builder.disable_built_in_roots(!self.built_in_roots && !self.native_roots);
+        #[cfg(all(feature = "hyper_native_tls", feature = "http2"))]
+        if alpn {
+            builder.request_alpns(&["h2", "http/1.1"]);
+        }
+        #[cfg(feature = "async_native_tls")]
+        return Ok(TlsConnector::from(builder));
+        #[cfg(feature = "hyper_native_tls")]
+        Ok(TlsConnector::from(builder.build().map_err(invalid)?))
+    }
+    /// The config used if none is set
+    pub(crate) fn default_ref() -> &'static TlsConfig {
+        static DEFAULT: OnceLock<TlsConfig> = OnceLock::new();
+        DEFAULT.get_or_init(TlsConfig::new)
+    }
+}
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("roots", &self.roots.len())
+            .field("built_in_roots", &self.built_in_roots)
+            .field("native_roots", &self.native_roots)
+            .field("client_cert", &self.client_cert.is_some())
+            .finish()
+    }
+}
+/// Why a TLS connection failed.
+/// Returned as [`Error::Tls`](crate::Error::Tls)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TlsError {
+    /// The server rejected the client certificate (or that there was none).
+    /// Contains the alert that it sent.
+    ///
+    /// Only reported with rustls. native-tls does not tell which alert was received,
+    /// so its handshake errors are returned as [`Error::Io`](crate::Error::Io)
+    ClientCertRejected(String),
+}
+impl std::fmt::Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::ClientCertRejected(alert) => {
+                write!(f, "server rejected the client certificate: {}", alert)
+            }
+        }
+    }
+}
+impl std::error::Error for TlsError {}
+impl From<TlsError> for io::Error {
+    fn from(e: TlsError) -> Self {
+        let kind = match e {
+            TlsError::ClientCertRejected(_) => io::ErrorKind::PermissionDenied,
+        };
+        io::Error::new(kind, e)
+    }
+}
+impl TlsError {
+    /// The `TlsError` inside of `e`
+    pub(crate) fn of(e: &io::Error) -> Option<TlsError> {
+        e.get_ref()?.downcast_ref::<TlsError>().cloned()
+    }
+}
+/// Replace `e` with a [`TlsError`], if the server rejected the client certificate.
+/// Only rustls errors are looked at, see [`TlsError::ClientCertRejected`]
+pub(crate) fn cert_rejected(e: io::Error) -> io::Error {
+    #[cfg(feature = "rustls_byoc")]
+    {
+        let alert = match e.get_ref().and_then(|i| i.downcast_ref::<RustlsError>()) {
+            Some(RustlsError::AlertReceived(
+                a @ (AlertDescription::BadCertificate
+                | AlertDescription::UnsupportedCertificate
+                | AlertDescription::CertificateRevoked
+                | AlertDescription::CertificateExpired
+                | AlertDescription::CertificateUnknown
+                | AlertDescription::UnknownCA
+                | AlertDescription::CertificateRequired),
+            )) => format!("{:?}", a),
+            _ => return e,
+        };
+        TlsError::ClientCertRejected(alert).into()
+    }
+    #[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+    e
+}
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{block_on, read_head, spawn, ReadExt, TcpListener, WriteExt};
+    use crate::Request;
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+
+    type Issued = (rcgen::Certificate, KeyPair);
+    fn ca() -> Issued {
+        let key = KeyPair::generate().unwrap();
+        let mut ca = CertificateParams::new(Vec::new()).unwrap();
+        ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca.distinguished_name.push(DnType::CommonName, "test CA");
+        (ca.self_signed(&key).unwrap(), key)
+    }
+    /// a certificate for `name` signed by `ca`
+    fn issue(ca: &Issued, name: &str, usage: ExtendedKeyUsagePurpose) -> Issued {
+        let key = KeyPair::generate().unwrap();
+        let mut cert = CertificateParams::new(vec![name.to_string()]).unwrap();
+        cert.distinguished_name.push(DnType::CommonName, name);
+        cert.extended_key_usages = vec![usage];
+        (cert.signed_by(&key, &ca.0, &ca.1).unwrap(), key)
+    }
+    fn server_cert(ca: &Issued) -> Issued {
+        issue(ca, "localhost", ExtendedKeyUsagePurpose::ServerAuth)
+    }
+    #[cfg(all(feature = "rustls_byoc", feature = "use_async_h1"))]
+    use futures_rustls::{rustls, TlsAcceptor};
+    #[cfg(all(feature = "rustls_byoc", feature = "use_hyper"))]
+    use tokio_rustls::{rustls, TlsAcceptor};
+    /// TLS server. Clients need a certificate signed by `client_ca`, if it is set
+    #[cfg(feature = "rustls_byoc")]
+    fn acceptor(cert: &Issued, client_ca: Option<&rcgen::Certificate>) -> TlsAcceptor {
+        use rustls::pki_types::PrivatePkcs8KeyDer;
+        let config = rustls::ServerConfig::builder();
+        let config = match client_ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                roots.add(ca.der().clone()).unwrap();
+                let verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(roots))
+                    .build()
+                    .unwrap();
+                config.with_client_cert_verifier(verifier)
+            }
+            None => config.with_no_client_auth(),
+        };
+        let config = config
+            .with_single_cert(
+                vec![cert.0.der().clone()],
+                PrivatePkcs8KeyDer::from(cert.1.serialize_der()).into(),
+            )
+            .unwrap();
+        TlsAcceptor::from(Arc::new(config))
+    }
+    #[cfg(feature = "async_native_tls")]
+    use async_native_tls::TlsAcceptor;
+    #[cfg(feature = "hyper_native_tls")]
+    use tokio_native_tls::TlsAcceptor;
+    #[cfg(any(feature = "async_native_tls", feature = "hyper_native_tls"))]
+    fn acceptor(cert: &Issued, _client_ca: Option<&rcgen::Certificate>) -> TlsAcceptor {
+        let id = native_tls::Identity::from_pkcs8(
+            cert.0.pem().as_bytes(),
+            cert.1.serialize_pem().as_bytes(),
+        )
+        .unwrap();
+        native_tls::TlsAcceptor::new(id).unwrap().into()
+    }
+    /// The first client is rejected, the second one gets a response
+    async fn server(listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<bool> {
+        let (mut stream, _) = listener.accept().await?;
+        assert!(acceptor.accept(&mut stream).await.is_err());
+        //wait for the client to see the alert
+        let _ = stream.read_to_end(&mut Vec::new()).await;
+
+        let (stream, _) = listener.accept().await?;
+        let mut stream = acceptor.accept(stream).await.map_err(invalid)?;
+        let head = read_head(&mut stream).await?;
+        assert!(head.starts_with("GET /tls HTTP/1.1\r\n"), "{}", head);
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabc")
+            .await?;
+        stream.flush().await?;
+        Ok(true)
+    }
+
+    #[test]
+    fn trust_root() {
+        block_on(async {
+            let ca = ca();
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let port = listener.local_addr()?.port();
+            let t = spawn(server(listener, acceptor(&server_cert(&ca), None)));
+            let uri = format!("https://localhost:{}/tls", port);
+
+            //does not know the CA
+            assert!(Request::get(&uri).exec().await.is_err());
+
+            let tls = TlsConfig::new()
+                .add_root_pem(ca.0.pem().as_bytes())?
+                .built_in_roots(false);
+            let mut aw = Request::get(&uri).tls(&tls).exec().await?;
+            assert_eq!(aw.text().await?, "abc", "wrong text");
+            assert!(tls.connector.get().is_some(), "not cached");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "proxies")]
+    #[test]
+    fn https_proxy() {
+        use crate::tcp::{handshake, proxy::HttpsProxy, tunnel_via_https_prx};
+        /// TLS to the proxy, then TLS to the origin inside of it
+        async fn server(listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<bool> {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await.map_err(invalid)?;
+            let head = read_head(&mut stream).await?;
+            assert!(
+                head.starts_with("CONNECT localhost:443 HTTP/1.1\r\n"),
+                "{}",
+                head
+            );
+            assert!(
+                head.contains("\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n"),
+                "{}",
+                head
+            );
+            stream.write_all(b"HTTP/1.1 200 Connected\r\n\r\n").await?;
+            stream.flush().await?;
+
+            let mut stream = acceptor.accept(stream).await.map_err(invalid)?;
+            let mut ping = [0u8; 4];
+            stream.read_exact(&mut ping).await?;
+            assert_eq!(&ping, b"ping");
+            stream.write_all(b"pong").await?;
+            stream.flush().await?;
+            Ok(true)
+        }
+        block_on(async {
+            let ca = ca();
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let port = listener.local_addr()?.port();
+            let t = spawn(server(listener, acceptor(&server_cert(&ca), None)));
+
+            let tls = TlsConfig::new()
+                .add_root_pem(ca.0.pem().as_bytes())?
+                .built_in_roots(false);
+            let options = crate::request::Options {
+                tls: Some(tls.clone()),
+                ..Default::default()
+            };
+            let prx = HttpsProxy::new("localhost", port).basic_auth("user", "pass");
+            let tunnel = tunnel_via_https_prx(
+                "localhost",
+                443,
+                &prx,
+                &tls,
+                &crate::resolve::SystemResolver,
+                &options,
+            )
+            .await?;
+            let mut stream = handshake(tls.connector()?, "localhost", tunnel, &options).await?;
+            stream.write_all(b"ping").await?;
+            stream.flush().await?;
+            let mut pong = [0u8; 4];
+            stream.read_exact(&mut pong).await?;
+            assert_eq!(&pong, b"pong");
+
+            assert!(tls.proxy_connector.get().is_some(), "not cached");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn invalid_root() {
+        assert!(TlsConfig::new().add_root_pem(b"no PEM").is_err());
+        assert!(TlsConfig::new().add_root_der(b"no DER").is_err());
+        let (ca, _) = ca();
+        let tls = TlsConfig::new().add_root_der(ca.der()).unwrap();
+        assert_eq!(tls.roots.len(), 1);
+    }
+    #[cfg(feature = "rustls_byoc")]
+    fn is_rejected(e: &Error) -> bool {
+        matches!(e, Error::Tls(TlsError::ClientCertRejected(_)))
+    }
+    #[cfg(feature = "rustls_byoc")]
+    #[test]
+    fn client_cert() {
+        block_on(async {
+            let ca = ca();
+            let client = issue(&ca, "client", ExtendedKeyUsagePurpose::ClientAuth);
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let port = listener.local_addr()?.port();
+            let acceptor = acceptor(&server_cert(&ca), Some(&ca.0));
+            let t = spawn(server(listener, acceptor));
+            let uri = format!("https://localhost:{}/tls", port);
+
+            let tls = TlsConfig::new()
+                .add_root_pem(ca.0.pem().as_bytes())?
+                .built_in_roots(false);
+            let e = Request::get(&uri).tls(&tls).exec().await.unwrap_err();
+            assert!(is_rejected(&e), "{:?}", e);
+
+            let tls = tls.client_cert_der(&[client.0.der()], &client.1.serialize_der())?;
+            let mut aw = Request::get(&uri).tls(&tls).exec().await?;
+            assert_eq!(aw.text().await?, "abc", "wrong text");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "rustls_byoc")]
+    #[test]
+    fn client_cert_pem() {
+        let ca = ca();
+        let (cert, key) = issue(&ca, "client", ExtendedKeyUsagePurpose::ClientAuth);
+        let (cert, key) = (cert.pem(), key.serialize_pem());
+        let tls = TlsConfig::new()
+            .client_cert_pem(cert.as_bytes(), key.as_bytes())
+            .unwrap();
+        assert!(tls.client_cert.is_some());
+        assert!(TlsConfig::new()
+            .client_cert_pem(cert.as_bytes(), b"no key")
+            .is_err());
+        assert!(TlsConfig::new()
+            .client_cert_pem(b"", key.as_bytes())
+            .is_err());
+    }
+    /// native-tls can not ask for client certificates, so use OpenSSL directly
+    #[cfg(all(
+        target_os = "linux",
+        any(feature = "async_native_tls", feature = "hyper_native_tls")
+    ))]
+    #[test]
+    fn client_cert() {
+        use openssl::{
+            pkcs12::Pkcs12,
+            pkey::PKey,
+            ssl::{SslAcceptor, SslMethod, SslVerifyMode},
+            x509::X509,
+        };
+        use std::io::{Read, Write};
+        let ca = ca();
+        let (cert, key) = server_cert(&ca);
+        let client = issue(&ca, "client", ExtendedKeyUsagePurpose::ClientAuth);
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor
+            .set_certificate(&X509::from_der(cert.der()).unwrap())
+            .unwrap();
+        acceptor
+            .set_private_key(&PKey::private_key_from_der(&key.serialize_der()).unwrap())
+            .unwrap();
+        acceptor
+            .cert_store_mut()
+            .add_cert(X509::from_der(ca.0.der()).unwrap())
+            .unwrap();
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        let acceptor = acceptor.build();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let t = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert!(acceptor.accept(&mut stream).is_err());
+            let _ = stream.read_to_end(&mut Vec::new());
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = acceptor.accept(stream).unwrap();
+            let mut head = [0u8; 18];
+            stream.read_exact(&mut head).unwrap();
+            assert_eq!(&head, b"GET /tls HTTP/1.1\r");
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabc")
+                .unwrap();
+            true
+        });
+
+        let p12 = Pkcs12::builder()
+            .name("client")
+            .pkey(&PKey::private_key_from_der(&client.1.serialize_der()).unwrap())
+            .cert(&X509::from_der(client.0.der()).unwrap())
+            .build2("secret")
+            .unwrap()
+            .to_der()
+            .unwrap();
+        block_on(async {
+            let uri = format!("https://localhost:{}/tls", port);
+            let tls = TlsConfig::new()
+                .add_root_pem(ca.0.pem().as_bytes())?
+                .built_in_roots(false);
+            //the rejection is not told apart from other handshake errors
+            let e = Request::get(&uri).tls(&tls).exec().await.unwrap_err();
+            assert!(matches!(e, Error::Io(_)), "{:?}", e);
+
+            assert!(tls.clone().client_cert_pkcs12(&p12, "wrong").is_err());
+            let tls = tls.client_cert_pkcs12(&p12, "secret")?;
+            let mut aw = Request::get(&uri).tls(&tls).exec().await?;
+            assert_eq!(aw.text().await?, "abc", "wrong text");
+            Ok(())
+        })
+        .unwrap();
+        assert!(t.join().unwrap(), "not cool");
+    }
+    /// HTTP/2 is negotiated with ALPN
+    #[cfg(all(target_os = "linux", feature = "hyper_native_tls", feature = "http2"))]
+    #[test]
+    fn alpn() {
+        use openssl::{
+            pkey::PKey,
+            ssl::{select_next_proto, AlpnError, SslAcceptor, SslMethod},
+            x509::X509,
+        };
+        use std::io::Read;
+        let ca = ca();
+        let (cert, key) = server_cert(&ca);
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor
+            .set_certificate(&X509::from_der(cert.der()).unwrap())
+            .unwrap();
+        acceptor
+            .set_private_key(&PKey::private_key_from_der(&key.serialize_der()).unwrap())
+            .unwrap();
+        acceptor.set_alpn_select_callback(|_, client| {
+            select_next_proto(b"\x02h2", client).ok_or(AlpnError::NOACK)
+        });
+        let acceptor = acceptor.build();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let t = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = acceptor.accept(stream).unwrap();
+            assert_eq!(stream.ssl().selected_alpn_protocol(), Some(&b"h2"[..]));
+            let mut preface = [0u8; 24];
+            stream.read_exact(&mut preface).unwrap();
+            assert_eq!(&preface, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+            true
+        });
+
+        block_on(async {
+            let uri = format!("https://localhost:{}/h2", port);
+            let tls = TlsConfig::new()
+                .add_root_pem(ca.0.pem().as_bytes())?
+                .built_in_roots(false);
+            //the server does not answer
+            assert!(Request::get(&uri).tls(&tls).exec().await.is_err());
+            Ok(())
+        })
+        .unwrap();
+        assert!(t.join().unwrap(), "not cool");
+    }
+}
@@ -0,0 +1,113 @@
+use std::io;
+use std::path::PathBuf;
+
+#[cfg(feature = "use_async_h1")]
+pub(crate) use async_std::os::unix::net::UnixStream;
+#[cfg(feature = "use_hyper")]
+pub(crate) use tokio::net::UnixStream;
+
+/// A Unix domain socket that is connected to instead of the host of the URL
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum UnixAddr {
+    Path(PathBuf),
+    /// Linux abstract namespace, the name without the leading NUL
+    #[cfg(target_os = "linux")]
+    Abstract(Vec<u8>),
+}
+impl UnixAddr {
+    pub(crate) async fn connect(&self) -> io::Result<UnixStream> {
+        match self {
+            UnixAddr::Path(path) => UnixStream::connect(path).await,
+            #[cfg(target_os = "linux")]
+            UnixAddr::Abstract(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                //does not block: the listener is there or not
+                let stream = std::os::unix::net::UnixStream::connect_addr(&addr)?;
+                stream.set_nonblocking(true)?;
+                #[cfg(feature = "use_hyper")]
+                return UnixStream::from_std(stream);
+                #[cfg(feature = "use_async_h1")]
+                return Ok(UnixStream::from(stream));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{block_on, read_head, spawn, WriteExt};
+    use crate::Request;
+    #[cfg(feature = "use_async_h1")]
+    use async_std::os::unix::net::{UnixListener, UnixStream};
+    #[cfg(feature = "use_hyper")]
+    use tokio::net::{UnixListener, UnixStream};
+
+    async fn server(mut stream: UnixStream) -> std::io::Result<bool> {
+        let head = read_head(&mut stream).await?;
+        assert!(
+            head.starts_with("GET /v1.43/version HTTP/1.1\r\n"),
+            "{}",
+            head
+        );
+        assert!(
+            head.to_lowercase().contains("\r\nhost: docker\r\n"),
+            "{}",
+            head
+        );
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabc")
+            .await?;
+        Ok(true)
+    }
+    #[test]
+    fn path() {
+        block_on(async {
+            let dir = std::env::temp_dir().join(format!("gahc-unix-{}", std::process::id()));
+            std::fs::create_dir_all(&dir)?;
+            let path = dir.join("docker.sock");
+            let _ = std::fs::remove_file(&path);
+            #[cfg(feature = "use_hyper")]
+            let listener = UnixListener::bind(&path)?;
+            #[cfg(feature = "use_async_h1")]
+            let listener = UnixListener::bind(&path).await?;
+            let t = spawn(async move { server(listener.accept().await?.0).await });
+
+            let mut aw = Request::get("http://docker/v1.43/version")
+                .unix_socket(&path)
+                .exec()
+                .await?;
+            assert_eq!(aw.text().await?, "abc", "wrong text");
+            assert!(t.await?, "not cool");
+            std::fs::remove_dir_all(&dir)?;
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn abstract_namespace() {
+        use std::os::linux::net::SocketAddrExt;
+        block_on(async {
+            let name = format!("gahc-test-{}", std::process::id());
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(&name)?;
+            let listener = std::os::unix::net::UnixListener::bind_addr(&addr)?;
+            listener.set_nonblocking(true)?;
+            #[cfg(feature = "use_hyper")]
+            let listener = UnixListener::from_std(listener)?;
+            #[cfg(feature = "use_async_h1")]
+            let listener = UnixListener::from(listener);
+            let t = spawn(async move { server(listener.accept().await?.0).await });
+
+            let mut aw = crate::Session::new()
+                .abstract_socket(&name)
+                .get("http://docker/v1.43/version")
+                .exec()
+                .await?;
+            assert_eq!(aw.text().await?, "abc", "wrong text");
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+use std::{future::Future, io, time::Duration};
+
+/// Limits how long a request may take.
+///
+/// All timeouts are unset by default.
+/// ```
+/// # use generic_async_http_client::{Request, Timeouts, Error};
+/// # use std::time::Duration;
+/// # async fn get() -> Result<(), Error> {
+///     let timeouts = Timeouts::new()
+///         .total(Duration::from_secs(30))
+///         .connect(Duration::from_secs(5))
+///         .body_idle(Duration::from_secs(10));
+///     let resp = Request::get("http://example.com/").timeouts(timeouts).exec().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Timeouts {
+    pub(crate) total: Option<Duration>,
+    pub(crate) connect: Option<Duration>,
+    pub(crate) tls_handshake: Option<Duration>,
+    pub(crate) first_byte: Option<Duration>,
+    pub(crate) body_idle: Option<Duration>,
+}
+impl Timeouts {
+    pub fn new() -> Timeouts {
+        Timeouts::default()
+    }
+    /// Deadline for the whole request, from connecting until the body of the response is read.
+    /// Redirects are included
+    pub fn total(mut self, d: Duration) -> Self {
+        self.total = Some(d);
+        self
+    }
+    /// Time to establish the connection (including a proxy handshake)
+    pub fn connect(mut self, d: Duration) -> Self {
+        self.connect = Some(d);
+        self
+    }
+    /// Time for the TLS handshake
+    pub fn tls_handshake(mut self, d: Duration) -> Self {
+        self.tls_handshake = Some(d);
+        self
+    }
+    /// Time from sending the request until the head of the response is received
+    pub fn first_byte(mut self, d: Duration) -> Self {
+        self.first_byte = Some(d);
+        self
+    }
+    /// Maximum time without receiving any part of the response body
+    pub fn body_idle(mut self, d: Duration) -> Self {
+        self.body_idle = Some(d);
+        self
+    }
+}
+
+/// Which timeout of [`Timeouts`] expired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    Total,
+    Connect,
+    TlsHandshake,
+    FirstByte,
+    BodyIdle,
+}
+impl std::fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            TimeoutKind::Total => "request timed out",
+            TimeoutKind::Connect => "connect timed out",
+            TimeoutKind::TlsHandshake => "TLS handshake timed out",
+            TimeoutKind::FirstByte => "no response in time",
+            TimeoutKind::BodyIdle => "body read timed out",
+        };
+        f.write_str(s)
+    }
+}
+impl std::error::Error for TimeoutKind {}
+
+impl From<TimeoutKind> for io::Error {
+    fn from(kind: TimeoutKind) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, kind)
+    }
+}
+/// Get the `TimeoutKind` back out of an `io::Error`
+pub(crate) fn kind_of(e: &io::Error) -> Option<TimeoutKind> {
+    if e.kind() != io::ErrorKind::TimedOut {
+        return None;
+    }
+    e.get_ref()?.downcast_ref::<TimeoutKind>().copied()
+}
+
+/// Run `fut`, but fail with an `io::Error` of `TimedOut` if it takes longer than `limit`
+#[cfg(any(feature = "use_hyper", feature = "use_async_h1"))]
+pub(crate) async fn timeout<F: Future>(
+    limit: Option<Duration>,
+    kind: TimeoutKind,
+    fut: F,
+) -> io::Result<F::Output> {
+    use futures::future::{select, Either};
+    let Some(limit) = limit else {
+        return Ok(fut.await);
+    };
+    #[cfg(feature = "use_hyper")]
+    let sleep = tokio::time::sleep(limit);
+    #[cfg(feature = "use_async_h1")]
+    let sleep = async_std::task::sleep(limit);
+
+    match select(std::pin::pin!(fut), std::pin::pin!(sleep)).await {
+        Either::Left((o, _)) => Ok(o),
+        Either::Right(_) => Err(kind.into()),
+    }
+}
+/// Without a backend there is no runtime and nothing to wait for
+#[cfg(not(any(feature = "use_hyper", feature = "use_async_h1")))]
+pub(crate) async fn timeout<F: Future>(
+    _limit: Option<Duration>,
+    _kind: TimeoutKind,
+    fut: F,
+) -> io::Result<F::Output> {
+    Ok(fut.await)
+}
+
+#[cfg(all(test, any(feature = "use_hyper", feature = "use_async_h1")))]
+mod tests {
+    use super::*;
+    use crate::tests::{block_on, listen_somewhere, read_head, spawn, TcpListener, WriteExt};
+    use crate::{Error, Request};
+
+    /// answer with `resp` and keep the connection open until the client closes it
+    async fn server(listener: TcpListener, resp: &'static [u8]) -> io::Result<bool> {
+        let (mut stream, _) = listener.accept().await?;
+        read_head(&mut stream).await?;
+        stream.write_all(resp).await?;
+        let _ = read_head(&mut stream).await;
+        Ok(true)
+    }
+    fn expect_timeout<T: std::fmt::Debug>(r: Result<T, Error>, kind: TimeoutKind) {
+        match r {
+            Err(Error::Timeout(k)) => assert_eq!(k, kind),
+            o => panic!("expected {:?}, got {:?}", kind, o),
+        }
+    }
+    /// a host name that never resolves
+    struct Stuck;
+    #[async_trait::async_trait]
+    impl crate::resolve::Resolve for Stuck {
+        async fn resolve(&self, _host: &str, _port: u16) -> io::Result<Vec<std::net::SocketAddr>> {
+            futures::future::pending().await
+        }
+    }
+    #[test]
+    fn connect() {
+        block_on(async {
+            let r = Request::get("http://stuck.example/")
+                .resolver(std::sync::Arc::new(Stuck))
+                .timeouts(Timeouts::new().connect(Duration::from_millis(100)));
+            //not the proxy of another test's environment
+            #[cfg(feature = "proxies")]
+            let r = r.proxy(std::sync::Arc::new(crate::proxy::NoProxy));
+            let r = r.exec().await;
+            expect_timeout(r, TimeoutKind::Connect);
+            Ok(())
+        })
+        .unwrap();
+    }
+    /// the server never answers the ClientHello
+    #[cfg(any(
+        feature = "rustls_byoc",
+        feature = "hyper_native_tls",
+        feature = "async_native_tls"
+    ))]
+    #[test]
+    fn tls_handshake() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(async move {
+                use crate::tests::ReadExt;
+                let (mut stream, _) = listener.accept().await?;
+                let _ = stream.read_to_end(&mut Vec::new()).await;
+                io::Result::Ok(true)
+            });
+
+            let r = Request::get(&format!("https://{}:{}/", host, port))
+                .timeouts(Timeouts::new().tls_handshake(Duration::from_millis(100)))
+                .exec()
+                .await;
+            expect_timeout(r, TimeoutKind::TlsHandshake);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn first_byte() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(listener, b""));
+
+            let r = Request::get(&format!("http://{}:{}/", host, port))
+                .timeouts(Timeouts::new().first_byte(Duration::from_millis(100)))
+                .exec()
+                .await;
+            expect_timeout(r, TimeoutKind::FirstByte);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn body_idle() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(
+                listener,
+                b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nabc",
+            ));
+
+            let mut resp = Request::get(&format!("http://{}:{}/", host, port))
+                .timeouts(Timeouts::new().body_idle(Duration::from_millis(100)))
+                .exec()
+                .await?;
+            expect_timeout(resp.text().await, TimeoutKind::BodyIdle);
+            drop(resp);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[test]
+    fn total() {
+        block_on(async {
+            let (listener, port, host) = listen_somewhere().await?;
+            let t = spawn(server(
+                listener,
+                b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nabc",
+            ));
+
+            let mut resp = Request::get(&format!("http://{}:{}/", host, port))
+                .timeout(Duration::from_millis(200))
+                .exec()
+                .await?;
+            expect_timeout(resp.content().await, TimeoutKind::Total);
+            drop(resp);
+            assert!(t.await?, "not cool");
+            Ok(())
+        })
+        .unwrap();
+    }
+}